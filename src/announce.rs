@@ -0,0 +1,760 @@
+//! This module is about building the Discord messages announcing matches.
+
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use crate::config::{AnnounceConfig, Config, ParseMode};
+use crate::emoji::Emojis;
+use crate::locale::Catalog;
+use crate::routing::Webhook;
+use crate::stratz;
+use crate::template::Renderer;
+use crate::{RefreshError, Require};
+
+/// The properties of a guild displayed in its match announcements.
+#[derive(Clone, Debug, Serialize)]
+pub struct GuildInfo {
+    pub id: i64,
+    pub name: String,
+    pub logo: String,
+}
+
+/// The result of a match, from the point of view of the guild players who took part in it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchResult {
+    None,
+    Victory,
+    Defeat,
+    Both,
+}
+
+/// Check whether a leaver status counts as abandoning the match, including never connecting to it for too long, as opposed to briefly disconnecting or not connecting to a match that did not start.
+fn is_abandon(status: &stratz::LeaverStatus) -> bool {
+    matches!(status, stratz::LeaverStatus::ABANDONED | stratz::LeaverStatus::AFK | stratz::LeaverStatus::DISCONNECTED_TOO_LONG | stratz::LeaverStatus::NEVER_CONNECTED_TOO_LONG)
+}
+
+/// A match announcement ready to be sent, along with the properties webhook filters are applied to.
+#[derive(Debug)]
+pub struct Announcement {
+    pub match_id: i64,
+    pub lobby_type: stratz::LobbyType,
+    pub game_mode: stratz::GameMode,
+    pub players: usize,
+    pub result: MatchResult,
+    /// The size of the largest party a guild player queued in.
+    pub stack: usize,
+    pub message: webhook::models::Message,
+}
+
+impl Announcement {
+    /// Check whether the announcement should be sent to the given [Webhook].
+    pub fn accepted_by(&self, webhook: &Webhook) -> bool {
+        webhook.filter.accepts(&self.lobby_type, &self.game_mode, self.players, &self.result, self.stack)
+    }
+}
+
+/// Check whether the announcement of a match should wait for Stratz to parse it, according to [AnnounceConfig::parse] and [AnnounceConfig::parse_timeout].
+pub fn awaits_parse(config: &AnnounceConfig, match_: &stratz::Match) -> bool {
+    if config.parse == ParseMode::Ignore || match_.parsed_date_time.is_some() {
+        return false
+    }
+    match match_.end_date_time {
+        Some(end) => chrono::Utc::now().timestamp() < end + config.parse_timeout,
+        None => false,
+    }
+}
+
+/// The data of a match announcement, passed to the [Renderer] templates.
+#[derive(Clone, Debug, Serialize)]
+pub struct MatchView {
+    pub id: i64,
+    pub guild: GuildInfo,
+    pub result: MatchResult,
+    pub result_name: String,
+    /// Whether a guild player abandoned the match, regardless of its result.
+    pub abandoned: bool,
+    pub lobby_type: stratz::LobbyType,
+    pub lobby_type_name: String,
+    pub game_mode: stratz::GameMode,
+    pub game_mode_name: String,
+    /// The duration of the match, formatted as `minutes:seconds`.
+    pub duration: String,
+    pub duration_seconds: i64,
+    /// The instant the match ended at, in RFC 3339 format.
+    pub end: String,
+    /// The side that won the match, regardless of which side the guild players were on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub winner: Option<Side>,
+    /// The translation of [MatchView::winner].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub winner_name: Option<String>,
+    /// The kills scored by the Radiant team, available only for parsed matches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radiant_kills: Option<i64>,
+    /// The kills scored by the Dire team, available only for parsed matches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dire_kills: Option<i64>,
+    /// The Dire buildings destroyed by the Radiant team.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radiant_destroyed: Option<BuildingsView>,
+    /// The Radiant buildings destroyed by the Dire team.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dire_destroyed: Option<BuildingsView>,
+    /// The instant of the first blood, formatted as `minutes:seconds`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_blood_time: Option<String>,
+    /// The average rank of the players of the match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_rank: Option<MedalView>,
+    /// The average rank of the players of the match, as computed by Stratz from their actual ranks instead of their medals.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual_rank: Option<MedalView>,
+    /// The medal of the bracket the match was played in, from 0 (Uncalibrated) to 8 (Immortal).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bracket: Option<u8>,
+    /// The translation of [MatchView::bracket].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bracket_name: Option<String>,
+    /// Whether Stratz has parsed the match, making all the stats available.
+    pub parsed: bool,
+    /// Whether the announcement will be edited once Stratz parses the match.
+    pub awaiting_parse: bool,
+    /// Whether all the players of the match are displayed, instead of the guild members only.
+    pub full_roster: bool,
+    /// The size of the largest party a guild player queued in, or 1 if none of them is known to have queued in a party.
+    ///
+    /// Without the full roster, the party members outside of the guild cannot be counted.
+    pub stack: usize,
+    pub radiant: Vec<PlayerView>,
+    pub dire: Vec<PlayerView>,
+}
+
+/// A side of the map.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Radiant,
+    Dire,
+}
+
+/// A rank medal, passed to the [Renderer] templates.
+#[derive(Clone, Debug, Serialize)]
+pub struct MedalView {
+    /// The rank as encoded by Stratz, with the medal as tens and the stars as units, such as `54` for Legend 4.
+    pub rank: i64,
+    /// The medal, from 0 (Uncalibrated) to 8 (Immortal).
+    pub medal: i64,
+    /// The stars of the medal, from 0 to 5.
+    pub stars: i64,
+    /// The translation of the medal, followed by its stars, such as `Legend 4`.
+    pub name: String,
+    /// The emoji of the medal, if the medal table has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emoji: Option<String>,
+}
+
+impl MedalView {
+    /// Create the view of the specified `rank`, as encoded by Stratz.
+    pub fn new(emojis: &Emojis, catalog: &Catalog, rank: i64) -> Self {
+        let medal = rank / 10;
+        let stars = rank % 10;
+        let name = match stars {
+            0 => catalog.get(&format!("medal.{medal}")),
+            _ => format!("{} {stars}", catalog.get(&format!("medal.{medal}"))),
+        };
+        Self {
+            rank,
+            medal,
+            stars,
+            name,
+            emoji: emojis.medal(rank).map(String::from),
+        }
+    }
+}
+
+/// The number of buildings of a side destroyed during a match.
+#[derive(Clone, Debug, Serialize)]
+pub struct BuildingsView {
+    pub towers: u32,
+    pub barracks: u32,
+}
+
+impl BuildingsView {
+    /// The number of towers of a side.
+    const TOWERS: u32 = 11;
+    /// The number of barracks of a side.
+    const BARRACKS: u32 = 6;
+
+    /// Count the destroyed buildings from the bitmasks of the ones still standing, as returned by Stratz.
+    fn destroyed(tower_status: Option<i64>, barracks_status: Option<i16>) -> Option<Self> {
+        let standing_towers = (tower_status? & ((1 << Self::TOWERS) - 1)).count_ones();
+        let standing_barracks = (barracks_status? & ((1 << Self::BARRACKS) - 1)).count_ones();
+        Some(Self {
+            towers: Self::TOWERS - standing_towers,
+            barracks: Self::BARRACKS - standing_barracks,
+        })
+    }
+}
+
+/// Determine the position from 1 (hard carry) to 5 (hard support) a player played at, from their lane and role as detected by Stratz.
+fn position(lane: Option<&stratz::Lane>, role: Option<&stratz::Role>) -> Option<u8> {
+    match (role?, lane) {
+        (stratz::Role::HARD_SUPPORT, _) => Some(5),
+        (stratz::Role::LIGHT_SUPPORT, _) => Some(4),
+        (stratz::Role::CORE, Some(stratz::Lane::SAFE_LANE)) => Some(1),
+        (stratz::Role::CORE, Some(stratz::Lane::MID_LANE)) => Some(2),
+        (stratz::Role::CORE, Some(stratz::Lane::OFF_LANE)) => Some(3),
+        _ => None,
+    }
+}
+
+/// Count the players of a side of the `roster` in each party, keyed by party ID.
+fn party_sizes(roster: &[stratz::Player], radiant: bool) -> HashMap<u8, usize> {
+    let mut sizes: HashMap<u8, usize> = HashMap::new();
+    for party_id in roster.iter().filter(|player| player.is_radiant == Some(radiant)).filter_map(|player| player.party_id) {
+        *sizes.entry(party_id).or_default() += 1;
+    }
+    sizes
+}
+
+/// Number the parties of the given players of a side, continuing from the `last` party number of the match.
+///
+/// Parties are sized with the `sizes` counted on the full roster if known; otherwise, only the displayed players can be counted,
+/// so players sharing their party with nobody displayed are neither numbered nor marked as solo, as their party members may be hidden.
+///
+/// Returns the size of the largest party including a guild member, or 1 if none of them is known to have queued in a party.
+fn mark_parties(players: &mut [PlayerView], sizes: Option<&HashMap<u8, usize>>, last: &mut usize) -> usize {
+    let mut stack: usize = 1;
+    for index in 0..players.len() {
+        let (Some(party_id), None) = (players[index].party_id, players[index].party) else {
+            continue
+        };
+        let members: Vec<usize> = (index..players.len()).filter(|other| players[*other].party_id == Some(party_id)).collect();
+        let size = sizes.and_then(|sizes| sizes.get(&party_id).copied()).unwrap_or(members.len()).max(members.len());
+        if size < 2 {
+            players[index].solo = sizes.is_some();
+            continue
+        }
+        *last += 1;
+        for member in members.iter() {
+            players[*member].party = Some(*last);
+        }
+        if members.iter().any(|member| players[*member].guild_member) {
+            stack = stack.max(size);
+        }
+    }
+    stack
+}
+
+/// Format a number of seconds as `minutes:seconds`.
+fn clock(seconds: i64) -> String {
+    let sign = if seconds < 0 { "-" } else { "" };
+    format!("{sign}{}:{:02}", seconds.abs() / 60, seconds.abs() % 60)
+}
+
+/// The data of a player of an announced match, passed to the [Renderer] templates.
+///
+/// Values missing from the Stratz response are left undefined.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlayerView {
+    /// Whether the player is a member of the guild.
+    pub guild_member: bool,
+    /// The Steam name of the player, or the translation of `label.anonymous`.
+    pub name: String,
+    /// The emoji of the hero of the player, or its name if it has no emoji.
+    pub hero: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hero_id: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hero_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kills: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deaths: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assists: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imp: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub networth: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gold_per_minute: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub experience_per_minute: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_hits: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denies: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hero_damage: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tower_damage: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hero_healing: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<u8>,
+    /// The award the player received at the end of the match, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub award: Option<stratz::Award>,
+    /// The translation of [PlayerView::award].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub award_name: Option<String>,
+    /// How the player left the match early, if they did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leaver_status: Option<stratz::LeaverStatus>,
+    /// The translation of [PlayerView::leaver_status].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leaver_status_name: Option<String>,
+    /// Whether the player left the match in a way that counts as an abandon.
+    pub abandoned: bool,
+    /// Whether Stratz detected the player feeding intentionally.
+    pub intentional_feeding: bool,
+    /// The lane the player spent the laning phase in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lane: Option<stratz::Lane>,
+    /// The translation of [PlayerView::lane].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lane_name: Option<String>,
+    /// The role the player played.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<stratz::Role>,
+    /// The translation of [PlayerView::role].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role_name: Option<String>,
+    /// The position from 1 to 5 the player played at, determined from [PlayerView::lane] and [PlayerView::role].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<u8>,
+    /// The ID of the party the player queued in, shared by all its members.
+    #[serde(skip)]
+    pub party_id: Option<u8>,
+    /// The number of the party the player queued in, counting the parties of the match from 1, if they did not queue alone.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub party: Option<usize>,
+    /// Whether the player is known to have queued alone.
+    pub solo: bool,
+    /// The current rank medal of the player, if they are a guild member and [AnnounceConfig::medals] is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub medal: Option<MedalView>,
+    /// The emojis or names of the items in the inventory of the player, if [AnnounceConfig::items] is enabled.
+    pub items: Vec<String>,
+    /// The emojis or names of the items in the backpack of the player, if [AnnounceConfig::items] is enabled.
+    pub backpack: Vec<String>,
+    /// The emoji or name of the neutral item of the player, if [AnnounceConfig::items] is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub neutral: Option<String>,
+    /// The stats selected by [AnnounceConfig::stats], in order, leaving out the ones missing from the Stratz response.
+    pub stats: Vec<StatView>,
+}
+
+/// A stat of a player that can be displayed in announcements.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stat {
+    Networth,
+    GoldPerMinute,
+    ExperiencePerMinute,
+    LastHits,
+    Denies,
+    HeroDamage,
+    TowerDamage,
+    HeroHealing,
+    Level,
+}
+
+impl Stat {
+    /// Get the value of the stat for the given player, if available.
+    fn value(&self, player: &stratz::Player) -> Option<i64> {
+        match self {
+            Self::Networth => player.networth,
+            Self::GoldPerMinute => player.gold_per_minute.map(i64::from),
+            Self::ExperiencePerMinute => player.experience_per_minute.map(i64::from),
+            Self::LastHits => player.num_last_hits.map(i64::from),
+            Self::Denies => player.num_denies.map(i64::from),
+            Self::HeroDamage => player.hero_damage,
+            Self::TowerDamage => player.tower_damage,
+            Self::HeroHealing => player.hero_healing,
+            Self::Level => player.level.map(i64::from),
+        }
+    }
+}
+
+/// A stat of a player, ready to be displayed.
+#[derive(Clone, Debug, Serialize)]
+pub struct StatView {
+    pub stat: Stat,
+    /// The translated short name of the stat, such as `GPM`.
+    pub name: String,
+    pub value: i64,
+    /// The value of the stat, abbreviated if large, such as `12.3k`.
+    pub text: String,
+}
+
+impl StatView {
+    /// Create the view of the given stat of a player, if available.
+    fn new(catalog: &Catalog, stat: Stat, player: &stratz::Player) -> Option<Self> {
+        let value = stat.value(player)?;
+        let text = match value.abs() >= 10_000 {
+            true => format!("{:.1}k", value as f64 / 1000.0),
+            false => value.to_string(),
+        };
+        Some(Self { stat, name: catalog.get_enum("stat", &stat), value, text })
+    }
+}
+
+/// Build the announcement of a match of the given guild, or return [None] if the match should not be announced.
+///
+/// The `details` of the match are merged in; for example, if its full roster is available, all its players are displayed, instead of the guild members only.
+pub fn build(config: &Config, emojis: &Emojis, renderer: &Renderer, match_: stratz::Match, details: &stratz::MatchDetails, guild: &GuildInfo) -> Result<Option<Announcement>, RefreshError> {
+    trace!("Ensuring the match ID exists...");
+    let id: i64 = match_.id.require(None, "match.id")?;
+    let awaiting_parse = awaits_parse(&config.announce, &match_);
+
+    trace!("Ensuring the player list exists...");
+    let players: Vec<Option<stratz::Player>> = match_.players.require(Some(id), "players")?;
+
+    if players.len() < config.announce.min_players {
+        trace!("Not announcing {id}, as it does not have enough players.");
+        return Ok(None)
+    }
+
+    trace!("Building announcement of match {id}...");
+
+    trace!("Ensuring the lobby type exists...");
+    let lobby_type: stratz::LobbyType = match_.lobby_type.require(Some(id), "lobbyType")?;
+    trace!("Ensuring the game mode exists...");
+    let game_mode: stratz::GameMode = match_.game_mode.require(Some(id), "gameMode")?;
+    trace!("Ensuring the duration exists...");
+    let duration = match_.duration_seconds.require(Some(id), "durationSeconds")?;
+    let duration: chrono::Duration = chrono::Duration::seconds(duration);
+    trace!("Ensuring the end date time exists...");
+    let end = match_.end_date_time.require(Some(id), "endDateTime")?;
+    let end = chrono::NaiveDateTime::from_timestamp_opt(end, 0).expect("timestamp to be valid");
+    let end = chrono::DateTime::<chrono::Utc>::from_utc(end, chrono::Utc);
+
+    trace!("Determining match result...");
+    let mut is_victory: bool = false;
+    let mut is_defeat: bool = false;
+    let mut abandoned: bool = false;
+    for (index, player) in players.iter().enumerate() {
+        trace!("Checking whether the player abandoned...");
+        if player.as_ref().and_then(|player| player.leaver_status.as_ref()).map(is_abandon).unwrap_or(false) {
+            abandoned = true;
+        }
+        trace!("Ensuring the victory property exists, or can be determined from the winning side...");
+        let player_result = player.as_ref().and_then(|player| {
+            player.is_victory.or_else(|| Some(player.is_radiant? == match_.did_radiant_win?))
+        });
+        let Some(player_result) = player_result else {
+            warn!("Ignoring result of players[{index}] of match {id}, as it is missing from the Stratz response.");
+            continue
+        };
+        trace!("Marking player's result...");
+        match player_result {
+            true => is_victory = true,
+            false => is_defeat = true,
+        }
+    }
+    let match_result = match (is_victory, is_defeat) {
+        (false, false) => MatchResult::None,
+        (true, false) => MatchResult::Victory,
+        (false, true) => MatchResult::Defeat,
+        (true, true) => MatchResult::Both,
+    };
+    trace!("Match result is: {match_result:?}");
+
+    // Ughhh, I'd really like to use map-reduce here...
+    trace!("Determining guild members...");
+    // The roster may also be fetched only to size parties, in which case it is not displayed.
+    let full_roster = config.announce.full_roster && details.roster.is_some();
+    // Without the full roster, only guild members are displayed.
+    let members: Option<HashSet<i64>> = full_roster.then(|| players.iter().flatten().filter_map(|player| player.steam_account_id).collect());
+    let displayed: Vec<Option<stratz::Player>> = match (full_roster, &details.roster) {
+        (true, Some(roster)) => roster.iter().cloned().map(Some).collect(),
+        _ => players.clone(),
+    };
+
+    trace!("Determining players' teams...");
+    let mut radiant_players: Vec<stratz::Player> = Vec::new();
+    let mut dire_players: Vec<stratz::Player> = Vec::new();
+    for (index, player) in displayed.into_iter().enumerate() {
+        trace!("Ensuring the player object exists...");
+        let Some(player) = player else {
+            warn!("Not displaying players[{index}] of match {id}, as it is missing from the Stratz response.");
+            continue
+        };
+        trace!("Ensuring the radiant property exists...");
+        let Some(player_team) = player.is_radiant else {
+            warn!("Not displaying players[{index}] of match {id}, as their team is missing from the Stratz response.");
+            continue
+        };
+        trace!("Marking player's team...");
+        match player_team {
+            true => radiant_players.push(player),
+            false => dire_players.push(player),
+        }
+    }
+    trace!("Teams determined successfully!");
+
+    trace!("Determining the score...");
+    let winner: Option<Side> = match_.did_radiant_win.map(|did_radiant_win| match did_radiant_win {
+        true => Side::Radiant,
+        false => Side::Dire,
+    });
+    let kills = |kills: &Option<Vec<Option<i64>>>| kills.as_ref().map(|kills| kills.iter().flatten().sum::<i64>());
+    let radiant_kills: Option<i64> = match_.stats.as_ref().and_then(|stats| kills(&stats.radiant_kills));
+    let dire_kills: Option<i64> = match_.stats.as_ref().and_then(|stats| kills(&stats.dire_kills));
+    // Each side destroys the buildings of the other one.
+    let radiant_destroyed = BuildingsView::destroyed(match_.tower_status_dire, match_.barracks_status_dire);
+    let dire_destroyed = BuildingsView::destroyed(match_.tower_status_radiant, match_.barracks_status_radiant);
+
+    trace!("Sorting players by position...");
+    let side = |players: Vec<stratz::Player>| {
+        let mut views: Vec<PlayerView> = players.into_iter().map(|player| PlayerView::new(emojis, renderer.catalog(), &config.announce, members.as_ref(), player)).collect();
+        // Players whose position is unknown are kept in the Stratz order, after the others.
+        // Parties are not grouped together, as their numbers already tell them apart, and lineups are easier to read in position order.
+        views.sort_by_key(|view| view.position.unwrap_or(u8::MAX));
+        views
+    };
+    let mut radiant = side(radiant_players);
+    let mut dire = side(dire_players);
+
+    trace!("Determining parties...");
+    let mut last_party: usize = 0;
+    let radiant_sizes = details.roster.as_deref().map(|roster| party_sizes(roster, true));
+    let dire_sizes = details.roster.as_deref().map(|roster| party_sizes(roster, false));
+    let stack = mark_parties(&mut radiant, radiant_sizes.as_ref(), &mut last_party).max(mark_parties(&mut dire, dire_sizes.as_ref(), &mut last_party));
+
+    trace!("Creating match view...");
+    let view = MatchView {
+        id,
+        guild: guild.clone(),
+        result_name: renderer.catalog().get_enum("result", &match_result),
+        result: match_result.clone(),
+        abandoned,
+        lobby_type_name: renderer.catalog().get_enum("lobby_type", &lobby_type),
+        lobby_type: lobby_type.clone(),
+        game_mode_name: renderer.catalog().get_enum("game_mode", &game_mode),
+        game_mode: game_mode.clone(),
+        duration: clock(duration.num_seconds()),
+        duration_seconds: duration.num_seconds(),
+        end: end.to_rfc3339(),
+        winner_name: winner.as_ref().map(|winner| renderer.catalog().get_enum("side", winner)),
+        winner,
+        radiant_kills,
+        dire_kills,
+        radiant_destroyed,
+        dire_destroyed,
+        first_blood_time: match_.first_blood_time.map(clock),
+        average_rank: match_.average_rank.map(|rank| MedalView::new(emojis, renderer.catalog(), rank.into())),
+        actual_rank: match_.actual_rank.map(|rank| MedalView::new(emojis, renderer.catalog(), rank.into())),
+        bracket: match_.bracket,
+        bracket_name: match_.bracket.map(|bracket| renderer.catalog().get(&format!("medal.{bracket}"))),
+        parsed: match_.parsed_date_time.is_some(),
+        awaiting_parse,
+        full_roster,
+        stack,
+        radiant,
+        dire,
+    };
+
+    trace!("Rendering match announcement...");
+    let message = renderer.render(&view).map_err(|source| RefreshError::Template { match_id: id, source })?;
+
+    Ok(Some(Announcement {
+        match_id: id,
+        lobby_type,
+        game_mode,
+        players: players.len(),
+        result: match_result,
+        stack,
+        message,
+    }))
+}
+
+impl PlayerView {
+    /// Create the view of a player, using placeholders for the data missing from the Stratz response.
+    fn new(emojis: &Emojis, catalog: &Catalog, config: &AnnounceConfig, members: Option<&HashSet<i64>>, player: stratz::Player) -> Self {
+        let guild_member = match (members, player.steam_account_id) {
+            (None, _) => true,
+            (Some(members), Some(id)) => members.contains(&id),
+            (Some(_), None) => false,
+        };
+        trace!("Determining the player's items...");
+        let item = |id: Option<i16>| id.filter(|id| *id != 0).filter(|_| config.items).map(|id| emojis.item(id.into()));
+        let items: Vec<String> = [player.item0_id, player.item1_id, player.item2_id, player.item3_id, player.item4_id, player.item5_id]
+            .into_iter().filter_map(item).collect();
+        let backpack: Vec<String> = [player.backpack0_id, player.backpack1_id, player.backpack2_id]
+            .into_iter().filter_map(item).collect();
+        let neutral: Option<String> = item(player.neutral0_id);
+        trace!("Determining the player's stats...");
+        let stats: Vec<StatView> = config.stats.iter().filter_map(|stat| StatView::new(catalog, *stat, &player)).collect();
+        trace!("Determining the player's award...");
+        let award = player.award.clone().filter(|award| award != &stratz::Award::NONE);
+        let award_name = award.as_ref().map(|award| catalog.get_enum("award", award));
+        trace!("Determining whether the player left the match...");
+        let leaver_status = player.leaver_status.clone().filter(|status| status != &stratz::LeaverStatus::NONE);
+        let leaver_status_name = leaver_status.as_ref().map(|status| catalog.get_enum("leaver_status", status));
+        let abandoned = leaver_status.as_ref().map(is_abandon).unwrap_or(false);
+        trace!("Determining the player's position...");
+        let lane = player.lane.clone().filter(|lane| lane != &stratz::Lane::UNKNOWN);
+        let lane_name = lane.as_ref().map(|lane| catalog.get_enum("lane", lane));
+        let role = player.role.clone().or_else(|| player.role_basic.clone()).filter(|role| role != &stratz::Role::UNKNOWN);
+        let role_name = role.as_ref().map(|role| catalog.get_enum("role", role));
+        let position = position(lane.as_ref(), role.as_ref());
+        trace!("Determining the player's medal...");
+        let medal = player.steam_account.as_ref()
+            .and_then(|steam| steam.season_rank)
+            .filter(|_| config.medals && guild_member)
+            .map(|rank| MedalView::new(emojis, catalog, rank.into()));
+        trace!("Determining the player's name...");
+        let name: String = player.steam_account.and_then(|steam: stratz::Steam| steam.name).unwrap_or_else(|| catalog.get("label.anonymous"));
+        trace!("Determining the player's hero emoji...");
+        let (hero_id, hero_name) = match player.hero {
+            Some(stratz::Hero { id, display_name }) => (id, display_name),
+            None => (None, None),
+        };
+        let hero: String = match (hero_id.and_then(|id| emojis.heroes.get(id.into())), &hero_name) {
+            (Some(emoji), _) => emoji.to_string(),
+            (None, Some(display_name)) => display_name.clone(),
+            (None, None) => String::from(":grey_question:"),
+        };
+        Self {
+            guild_member,
+            name,
+            hero,
+            hero_id,
+            hero_name,
+            kills: player.kills,
+            deaths: player.deaths,
+            assists: player.assists,
+            imp: player.imp,
+            networth: player.networth,
+            gold_per_minute: player.gold_per_minute,
+            experience_per_minute: player.experience_per_minute,
+            last_hits: player.num_last_hits,
+            denies: player.num_denies,
+            hero_damage: player.hero_damage,
+            tower_damage: player.tower_damage,
+            hero_healing: player.hero_healing,
+            level: player.level,
+            award,
+            award_name,
+            leaver_status,
+            leaver_status_name,
+            abandoned,
+            intentional_feeding: player.intentional_feeding.unwrap_or(false),
+            lane,
+            lane_name,
+            role,
+            role_name,
+            position,
+            party_id: player.party_id,
+            party: None,
+            solo: false,
+            medal,
+            items,
+            backpack,
+            neutral,
+            stats,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routing::Filter;
+    use super::*;
+
+    /// Build a player of the given side, as returned by Stratz.
+    fn player(steam_account_id: i64, radiant: bool, party_id: Option<u8>) -> stratz::Player {
+        let player = serde_json::json!({"steamAccountId": steam_account_id, "isRadiant": radiant, "partyId": party_id});
+        serde_json::from_value(player).expect("player to be valid")
+    }
+
+    /// Build the views of the given `players`, the ones with a Steam account ID lower than 10 being guild members.
+    fn views(players: &[stratz::Player]) -> Vec<PlayerView> {
+        let catalog = Catalog::load("en").unwrap();
+        let members: HashSet<i64> = (0..10).collect();
+        players.iter()
+            .map(|player| PlayerView::new(&Emojis::default(), &catalog, &AnnounceConfig::default(), Some(&members), player.clone()))
+            .collect()
+    }
+
+    /// Get the party number and solo flag of each player.
+    fn parties(players: &[PlayerView]) -> Vec<(Option<usize>, bool)> {
+        players.iter().map(|player| (player.party, player.solo)).collect()
+    }
+
+    #[test]
+    fn sizes_parties_of_each_side_from_the_roster() {
+        let roster = [player(1, true, Some(0)), player(11, true, Some(0)), player(12, true, Some(0)), player(13, true, None), player(21, false, Some(0)), player(22, false, Some(0))];
+
+        assert_eq!(party_sizes(&roster, true), HashMap::from([(0, 3)]));
+        assert_eq!(party_sizes(&roster, false), HashMap::from([(0, 2)]));
+    }
+
+    #[test]
+    fn marks_solo_players_only_with_the_roster() {
+        let mut players = views(&[player(1, true, Some(0))]);
+        let sizes = HashMap::from([(0, 1)]);
+        let mut last: usize = 0;
+        assert_eq!(mark_parties(&mut players, Some(&sizes), &mut last), 1);
+        assert_eq!(parties(&players), vec![(None, true)]);
+        assert_eq!(last, 0);
+
+        let mut players = views(&[player(1, true, Some(0))]);
+        assert_eq!(mark_parties(&mut players, None, &mut last), 1);
+        assert_eq!(parties(&players), vec![(None, false)]);
+    }
+
+    #[test]
+    fn sizes_parties_with_hidden_members_from_the_roster() {
+        let displayed = [player(1, true, Some(0)), player(2, true, Some(1))];
+        let sizes = HashMap::from([(0, 1), (1, 3)]);
+
+        let mut players = views(&displayed);
+        let mut last: usize = 0;
+        assert_eq!(mark_parties(&mut players, Some(&sizes), &mut last), 3);
+        assert_eq!(parties(&players), vec![(None, true), (Some(1), false)]);
+
+        let mut players = views(&displayed);
+        let mut last: usize = 0;
+        assert_eq!(mark_parties(&mut players, None, &mut last), 1);
+        assert_eq!(parties(&players), vec![(None, false), (None, false)]);
+    }
+
+    #[test]
+    fn numbers_parties_across_sides() {
+        let mut radiant = views(&[player(1, true, Some(0)), player(11, true, Some(1)), player(2, true, Some(0)), player(12, true, Some(1))]);
+        let mut dire = views(&[player(3, false, Some(0)), player(4, false, Some(0)), player(5, false, Some(0))]);
+        let mut last: usize = 0;
+
+        let radiant_stack = mark_parties(&mut radiant, None, &mut last);
+        let dire_stack = mark_parties(&mut dire, None, &mut last);
+
+        assert_eq!(parties(&radiant), vec![(Some(1), false), (Some(2), false), (Some(1), false), (Some(2), false)]);
+        assert_eq!(parties(&dire), vec![(Some(3), false), (Some(3), false), (Some(3), false)]);
+        assert_eq!(last, 3);
+        assert_eq!((radiant_stack, dire_stack), (2, 3));
+    }
+
+    #[test]
+    fn ignores_parties_of_other_players_in_the_stack() {
+        let mut players = views(&[player(1, true, Some(0)), player(11, true, Some(1)), player(12, true, Some(1)), player(13, true, Some(1))]);
+        let sizes = HashMap::from([(0, 2), (1, 3)]);
+        let mut last: usize = 0;
+
+        assert_eq!(mark_parties(&mut players, Some(&sizes), &mut last), 2);
+        assert_eq!(parties(&players), vec![(Some(1), false), (Some(2), false), (Some(2), false), (Some(2), false)]);
+    }
+
+    #[test]
+    fn filters_on_parties_with_party_only() {
+        let filter = Filter { party_only: true, ..Filter::default() };
+        let accepts = |stack| filter.accepts(&stratz::LobbyType::RANKED, &stratz::GameMode::ALL_PICK_RANKED, 1, &MatchResult::Victory, stack);
+
+        assert!(!accepts(1));
+        assert!(accepts(2));
+        assert!(Filter::default().accepts(&stratz::LobbyType::RANKED, &stratz::GameMode::ALL_PICK_RANKED, 1, &MatchResult::Victory, 1));
+    }
+}
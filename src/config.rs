@@ -1,22 +1,412 @@
-//! This module is about fetching configuration values and parsing them appropriately.
+//! This module is about loading the configuration of the bot and validating it.
 
+use std::collections::HashSet;
+use std::path::Path;
 use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::announce::Stat;
+use crate::emoji::EmojiConfig;
+use crate::locale;
+use crate::routing::{Filter, Webhook};
+use crate::schedule::{ScheduleRule, MAX_PERIOD};
+use crate::stratz;
+use crate::stratz::RetryConfig;
+use crate::template::{TemplateConfig, MAX_FIELDS};
 
-/// Get the ID of the Dota guild to follow from the `FOLLOWED_GUILD_ID` envvar.
-pub fn followed_guild_id() -> i64 {
-    let value = std::env::var("FOLLOWED_GUILD_ID").expect("Missing FOLLOWED_GUILD_ID envvar");
-    let value = i64::from_str(&value).expect("Failed to parse FOLLOWED_GUILD_ID envvar");
-    value
+/// The configuration of the bot, loaded once at startup with [Config::load].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The [Stratz API key](https://stratz.com/api).
+    ///
+    /// Overridden by the `STRATZ_JWT` envvar.
+    #[serde(default)]
+    pub stratz_jwt: String,
+    /// The path of the file where the bot state is persisted.
+    ///
+    /// Overridden by the `STATE_PATH` envvar.
+    #[serde(default = "default_state_path")]
+    pub state_path: String,
+    /// The emoji tables to use in announcements.
+    #[serde(default)]
+    pub emojis: EmojiConfig,
+    /// The templates announcements are rendered with.
+    #[serde(default)]
+    pub template: TemplateConfig,
+    /// How failed requests to Stratz should be retried.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// How matches should be scanned.
+    #[serde(default)]
+    pub scan: ScanConfig,
+    /// How matches should be announced.
+    #[serde(default)]
+    pub announce: AnnounceConfig,
+    /// The Dota guilds to follow.
+    ///
+    /// Overridden by the comma-separated `FOLLOWED_GUILD_IDS` or `FOLLOWED_GUILD_ID` envvars.
+    #[serde(default)]
+    pub guilds: Vec<GuildConfig>,
+    /// The Discord webhooks matches of guilds without their own webhook are routed to.
+    ///
+    /// If empty, an unfiltered webhook is read from the `DISCORD_WEBHOOK_URL` envvar.
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
 }
 
-/// Get the [Stratz API key](https://stratz.com/api) from the `STRATZ_JWT` envvar.
-pub fn stratz_jwt() -> String {
-    let value = std::env::var("STRATZ_JWT").expect("Missing STRATZ_JWT envvar");
-    value
+/// Configuration of the match scans.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScanConfig {
+    /// The number of seconds elapsed between two match scans, when no [ScanConfig::schedule] rule applies.
+    ///
+    /// Overridden by the `MATCH_SCAN_PERIOD` envvar.
+    #[serde(default = "default_scan_period")]
+    pub period: u64,
+    /// Rules overriding [ScanConfig::period] during some hours of the day, in local time; the first matching rule wins.
+    #[serde(default)]
+    pub schedule: Vec<ScheduleRule>,
+    /// The amount of matches to request on every scan.
+    ///
+    /// Overridden by the `MATCH_SCAN_TAKE` envvar.
+    #[serde(default = "default_scan_take")]
+    pub take: i64,
+    /// The maximum number of pages of [ScanConfig::take] matches to request on every scan, when looking for all the matches played since the last scan.
+    #[serde(default = "default_scan_max_pages")]
+    pub max_pages: i64,
 }
 
-/// Get the Discord webhook URL from the `DISCORD_WEBHOOK_URL` envvar.
-pub fn discord_webhook_url() -> String {
-    let value = std::env::var("DISCORD_WEBHOOK_URL").expect("Missing DISCORD_WEBHOOK_URL envvar");
-    value
-}
\ No newline at end of file
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            period: default_scan_period(),
+            schedule: Vec::new(),
+            take: default_scan_take(),
+            max_pages: default_scan_max_pages(),
+        }
+    }
+}
+
+/// Configuration of the match announcements.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnnounceConfig {
+    /// The minimum number of players that must be in a match for it to be announced.
+    ///
+    /// Overridden by the `MATCH_ANNOUNCE_PLAYERS` envvar.
+    #[serde(default = "default_announce_min_players")]
+    pub min_players: usize,
+    /// The number of times sending an announcement to a webhook is attempted, once per scan, before giving up on it.
+    #[serde(default = "default_announce_max_attempts")]
+    pub max_attempts: u32,
+    /// The locale of the strings displayed in announcements, such as `en` or `it`.
+    ///
+    /// Overridden by the `ANNOUNCE_LOCALE` envvar.
+    #[serde(default = "default_announce_locale")]
+    pub locale: String,
+    /// The stats displayed next to the KDA of every player, in order.
+    #[serde(default)]
+    pub stats: Vec<Stat>,
+    /// Whether the final inventory of every player should be displayed.
+    #[serde(default)]
+    pub items: bool,
+    /// Whether all the players of every match should be displayed, instead of the guild members only, at the cost of an additional Stratz request per scan.
+    #[serde(default)]
+    pub full_roster: bool,
+    /// Whether the current rank medal of every guild player should be displayed.
+    #[serde(default)]
+    pub medals: bool,
+    /// Whether the ranks of guild members should be tracked, announcing their promotions and demotions, at the cost of an additional Stratz request per scan.
+    #[serde(default)]
+    pub ranks: bool,
+    /// How matches Stratz has not parsed yet, and which therefore lack most stats, should be announced.
+    #[serde(default)]
+    pub parse: ParseMode,
+    /// The number of seconds after the end of a match Stratz is waited for to parse it, with [ParseMode::Hold] or [ParseMode::Edit].
+    #[serde(default = "default_announce_parse_timeout")]
+    pub parse_timeout: i64,
+}
+
+/// How matches Stratz has not parsed yet should be announced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseMode {
+    /// Announce them right away, without the stats that are not available yet.
+    #[default]
+    Ignore,
+    /// Hold their announcements, and the ones of all the following matches, until they are parsed or the timeout expires.
+    Hold,
+    /// Announce them right away, then edit the announcements once they are parsed, until the timeout expires.
+    Edit,
+}
+
+impl Default for AnnounceConfig {
+    fn default() -> Self {
+        Self {
+            min_players: default_announce_min_players(),
+            max_attempts: default_announce_max_attempts(),
+            locale: default_announce_locale(),
+            stats: Vec::new(),
+            items: false,
+            full_roster: false,
+            medals: false,
+            ranks: false,
+            parse: ParseMode::default(),
+            parse_timeout: default_announce_parse_timeout(),
+        }
+    }
+}
+
+
+/// Configuration of a followed Dota guild.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuildConfig {
+    /// The ID of the guild.
+    pub id: i64,
+    /// The URL of a Discord webhook all matches of this guild should be announced on, instead of [Config::webhooks].
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+}
+
+fn default_state_path() -> String {
+    String::from("state.json")
+}
+
+fn default_scan_period() -> u64 {
+    60 * 30
+}
+
+fn default_scan_take() -> i64 {
+    10
+}
+
+fn default_scan_max_pages() -> i64 {
+    5
+}
+
+fn default_announce_min_players() -> usize {
+    1
+}
+
+fn default_announce_max_attempts() -> u32 {
+    5
+}
+
+fn default_announce_parse_timeout() -> i64 {
+    60 * 60 * 2
+}
+
+fn default_announce_locale() -> String {
+    String::from(locale::FALLBACK_LOCALE)
+}
+
+/// Error enumeration for possible configuration errors.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    #[error("could not read configuration file: {0}")]
+    Read(#[from] std::io::Error),
+    /// The configuration file is not valid TOML, or does not match the expected structure.
+    #[error("could not parse configuration file: {0}")]
+    Parse(#[from] toml::de::Error),
+    /// An envvar override could not be parsed.
+    #[error("could not parse {0} envvar")]
+    Env(&'static str),
+    /// A configuration value is not acceptable.
+    #[error("{0}")]
+    Invalid(String),
+}
+
+impl Config {
+    /// Load the configuration from the TOML file at the path specified by the `CONFIG_PATH` envvar, defaulting to `config.toml`, then apply envvar overrides and validate it.
+    ///
+    /// A missing configuration file is not an error, so that the bot can still be configured through envvars only.
+    ///
+    /// All the problems found are returned at once.
+    pub fn load() -> Result<Self, Vec<ConfigError>> {
+        let path = std::env::var("CONFIG_PATH").unwrap_or_else(|_| String::from("config.toml"));
+        let mut config = Self::from_file(Path::new(&path)).map_err(|e| vec![e])?;
+        let mut errors = config.apply_env();
+        errors.extend(config.validate());
+        match errors.is_empty() {
+            true => Ok(config),
+            false => Err(errors),
+        }
+    }
+
+    /// Parse the configuration from the TOML file at the specified `path`.
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        trace!("Reading configuration from {path:?}...");
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!("Configuration file {path:?} does not exist, using envvars only.");
+                String::new()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        trace!("Parsing configuration...");
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Override configuration values with the ones specified in envvars, returning the overrides that could not be parsed.
+    fn apply_env(&mut self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        if let Ok(value) = std::env::var("STRATZ_JWT") {
+            self.stratz_jwt = value;
+        }
+        if let Ok(value) = std::env::var("STATE_PATH") {
+            self.state_path = value;
+        }
+        if let Ok(value) = std::env::var("MATCH_SCAN_PERIOD") {
+            match u64::from_str(&value) {
+                Ok(period) => self.scan.period = period,
+                Err(_) => errors.push(ConfigError::Env("MATCH_SCAN_PERIOD")),
+            }
+        }
+        if let Ok(value) = std::env::var("MATCH_SCAN_TAKE") {
+            match i64::from_str(&value) {
+                Ok(take) => self.scan.take = take,
+                Err(_) => errors.push(ConfigError::Env("MATCH_SCAN_TAKE")),
+            }
+        }
+        if let Ok(value) = std::env::var("MATCH_ANNOUNCE_PLAYERS") {
+            match usize::from_str(&value) {
+                Ok(min_players) => self.announce.min_players = min_players,
+                Err(_) => errors.push(ConfigError::Env("MATCH_ANNOUNCE_PLAYERS")),
+            }
+        }
+        if let Ok(value) = std::env::var("ANNOUNCE_LOCALE") {
+            self.announce.locale = value;
+        }
+        let guild_ids = ["FOLLOWED_GUILD_IDS", "FOLLOWED_GUILD_ID"].into_iter()
+            .find_map(|name| std::env::var(name).ok().map(|value| (name, value)));
+        if let Some((name, value)) = guild_ids {
+            match value.split(',').map(|id| i64::from_str(id.trim())).collect::<Result<Vec<i64>, _>>() {
+                Ok(ids) => self.guilds = ids.into_iter().map(|id| GuildConfig { id, webhook_url: None }).collect(),
+                Err(_) => errors.push(ConfigError::Env(name)),
+            }
+        }
+        if self.webhooks.is_empty() {
+            if let Ok(url) = std::env::var("DISCORD_WEBHOOK_URL") {
+                self.webhooks.push(Webhook { name: String::from("default"), url, filter: Filter::default(), rank_changes: true });
+            }
+        }
+
+        errors
+    }
+
+    /// Check that the configuration values make sense, returning all the problems found.
+    fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        if self.stratz_jwt.is_empty() {
+            errors.push(ConfigError::Invalid(String::from("stratz_jwt must be set")));
+        }
+        if self.scan.period == 0 {
+            errors.push(ConfigError::Invalid(String::from("scan.period must be greater than 0")));
+        }
+        if self.scan.period > MAX_PERIOD {
+            errors.push(ConfigError::Invalid(format!("scan.period must be at most {MAX_PERIOD}")));
+        }
+        for (index, rule) in self.scan.schedule.iter().enumerate() {
+            if rule.period == 0 {
+                errors.push(ConfigError::Invalid(format!("scan.schedule[{index}].period must be greater than 0")));
+            }
+            if rule.period > MAX_PERIOD {
+                errors.push(ConfigError::Invalid(format!("scan.schedule[{index}].period must be at most {MAX_PERIOD}")));
+            }
+            if !rule.hours.fits(23) {
+                errors.push(ConfigError::Invalid(format!("scan.schedule[{index}].hours must be between 0 and 23")));
+            }
+            if !rule.weekdays.fits(7) {
+                errors.push(ConfigError::Invalid(format!("scan.schedule[{index}].weekdays must be between 0 and 7")));
+            }
+        }
+        if self.scan.take <= 0 {
+            errors.push(ConfigError::Invalid(String::from("scan.take must be greater than 0")));
+        }
+        if self.announce.max_attempts == 0 {
+            errors.push(ConfigError::Invalid(String::from("announce.max_attempts must be greater than 0")));
+        }
+        if !locale::available().contains(&self.announce.locale.as_str()) {
+            errors.push(ConfigError::Invalid(format!("announce.locale must be one of: {}", locale::available().join(", "))));
+        }
+        if self.announce.parse_timeout < 0 {
+            errors.push(ConfigError::Invalid(String::from("announce.parse_timeout must not be negative")));
+        }
+        if self.scan.max_pages <= 0 {
+            errors.push(ConfigError::Invalid(String::from("scan.max_pages must be greater than 0")));
+        }
+        if self.template.fields.len() > MAX_FIELDS {
+            errors.push(ConfigError::Invalid(format!("template.fields must contain at most {MAX_FIELDS} fields")));
+        }
+
+        if self.guilds.is_empty() {
+            errors.push(ConfigError::Invalid(String::from("at least one guild must be followed")));
+        }
+        let mut guild_ids = HashSet::new();
+        for guild in self.guilds.iter() {
+            if !guild_ids.insert(guild.id) {
+                errors.push(ConfigError::Invalid(format!("guild {} is followed more than once", guild.id)));
+            }
+            if guild.webhook_url.is_none() && self.webhooks.is_empty() {
+                errors.push(ConfigError::Invalid(format!("guild {} has no webhook_url, and no webhooks are configured", guild.id)));
+            }
+            if let Some(url) = &guild.webhook_url {
+                if !is_url(url) {
+                    errors.push(ConfigError::Invalid(format!("webhook_url of guild {} is not a valid URL", guild.id)));
+                }
+            }
+        }
+
+        let mut webhook_names = HashSet::new();
+        for webhook in self.webhooks.iter() {
+            if !webhook_names.insert(&webhook.name) {
+                errors.push(ConfigError::Invalid(format!("webhook {} is configured more than once", &webhook.name)));
+            }
+            if !is_url(&webhook.url) {
+                errors.push(ConfigError::Invalid(format!("url of webhook {} is not a valid URL", &webhook.name)));
+            }
+            for lobby_type in webhook.filter.lobby_types.iter().flatten() {
+                if let stratz::LobbyType::Other(name) = lobby_type {
+                    errors.push(ConfigError::Invalid(format!("webhook {} filters on unknown lobby type {name}", &webhook.name)));
+                }
+            }
+            for game_mode in webhook.filter.game_modes.iter().flatten() {
+                if let stratz::GameMode::Other(name) = game_mode {
+                    errors.push(ConfigError::Invalid(format!("webhook {} filters on unknown game mode {name}", &webhook.name)));
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Check whether any enabled option needs the details of matches, which are fetched with an additional Stratz request per scan.
+    ///
+    /// Filtering on parties needs them too, as the guild matches query only returns the guild members, hiding the other members of their parties.
+    pub fn needs_details(&self) -> bool {
+        self.announce.full_roster || self.webhooks.iter().any(|webhook| webhook.filter.party_only)
+    }
+
+    /// Get the Discord webhooks matches of the guild having the specified `guild_id` should be routed to.
+    ///
+    /// If the guild has its own [GuildConfig::webhook_url], all its matches are announced there; otherwise, they are routed through [Config::webhooks].
+    pub fn guild_webhooks(&self, guild_id: i64) -> Vec<Webhook> {
+        let own_url = self.guilds.iter()
+            .find(|guild| guild.id == guild_id)
+            .and_then(|guild| guild.webhook_url.clone());
+        match own_url {
+            Some(url) => vec![Webhook { name: guild_id.to_string(), url, filter: Filter::default(), rank_changes: true }],
+            None => self.webhooks.clone(),
+        }
+    }
+}
+
+/// Check whether the given string looks like an HTTP(S) URL.
+fn is_url(value: &str) -> bool {
+    value.starts_with("https://") || value.starts_with("http://")
+}
@@ -0,0 +1,202 @@
+//! This module is about performing requests to the [Discord REST API](https://discord.com/developers/docs/reference), either with a bot token or through webhooks.
+
+use std::time::Duration;
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+/// The default base URL of the Discord REST API.
+pub const DEFAULT_API_URL: &str = "https://discord.com/api/v10";
+
+/// The maximum number of times a rate limited request is retried.
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+
+/// Error enumeration for possible Discord API errors.
+#[derive(Debug, thiserror::Error)]
+pub enum DiscordError {
+    /// An error occurred while performing a request to Discord.
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    /// Discord answered with an error status.
+    #[error("Discord answered with HTTP status {status}: {body}")]
+    Status {
+        /// The status Discord answered with.
+        status: StatusCode,
+        /// The body of the response, usually describing the error.
+        body: String,
+    },
+}
+
+/// Where custom emojis are uploaded to.
+#[derive(Clone, Debug)]
+pub enum EmojiOwner {
+    /// The application of the bot, making the emojis usable everywhere by the bot and its webhooks.
+    Application(String),
+    /// A Discord server, making the emojis usable by anyone in it.
+    Guild(String),
+}
+
+impl EmojiOwner {
+    /// Get the API path of the emojis of this owner.
+    fn path(&self) -> String {
+        match self {
+            Self::Application(id) => format!("/applications/{id}/emojis"),
+            Self::Guild(id) => format!("/guilds/{id}/emojis"),
+        }
+    }
+}
+
+/// A custom Discord emoji.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Emoji {
+    /// The snowflake of the emoji.
+    pub id: String,
+    /// The name of the emoji.
+    pub name: String,
+    /// Whether the emoji is animated.
+    #[serde(default)]
+    pub animated: bool,
+}
+
+impl Emoji {
+    /// Get the markdown used to display the emoji in a message, such as `<:antimage:958248644652458005>`.
+    pub fn markdown(&self) -> String {
+        match self.animated {
+            true => format!("<a:{}:{}>", &self.name, &self.id),
+            false => format!("<:{}:{}>", &self.name, &self.id),
+        }
+    }
+}
+
+/// Body of the request creating a new custom emoji.
+#[derive(Clone, Debug, Serialize)]
+struct NewEmoji<'a> {
+    name: &'a str,
+    /// The image of the emoji, as a `data:` URI.
+    image: String,
+}
+
+/// Response listing the emojis of an application, which are wrapped in an object unlike the ones of guilds.
+#[derive(Clone, Debug, Deserialize)]
+struct ApplicationEmojis {
+    items: Vec<Emoji>,
+}
+
+/// The subset of the application object the bot needs.
+#[derive(Clone, Debug, Deserialize)]
+struct Application {
+    id: String,
+}
+
+/// A Discord REST API client, authenticated as a bot.
+#[derive(Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    token: String,
+}
+
+impl Client {
+    /// Create a new client for the API at `base_url`, authenticating with the bot `token`.
+    pub fn new(http: reqwest::Client, base_url: impl Into<String>, token: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self { http, base_url, token: token.into() }
+    }
+
+    /// Perform a request, waiting and retrying if rate limited, and parse its JSON response.
+    async fn request<T: DeserializeOwned, B: Serialize>(&self, method: Method, path: &str, body: Option<&B>) -> Result<T, DiscordError> {
+        let mut attempt: u32 = 0;
+        loop {
+            trace!("Performing {method} {path}, attempt {}...", attempt + 1);
+            let mut request = self.http.request(method.clone(), format!("{}{path}", &self.base_url))
+                .header(reqwest::header::AUTHORIZATION, format!("Bot {}", &self.token));
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            let resp = request.send().await?;
+
+            let status = resp.status();
+            if status == StatusCode::TOO_MANY_REQUESTS && attempt < MAX_RATE_LIMIT_RETRIES {
+                let retry_after = resp.headers().get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .map(Duration::from_secs_f64)
+                    .unwrap_or(Duration::from_secs(1));
+                warn!("Rate limited by Discord, retrying after {retry_after:?}...");
+                tokio::time::sleep(retry_after).await;
+                attempt += 1;
+                continue;
+            }
+            let resp = check_status(resp).await?;
+            trace!("Parsing response...");
+            return Ok(resp.json::<T>().await?);
+        }
+    }
+
+    /// Get the ID of the application of the bot.
+    pub async fn application_id(&self) -> Result<String, DiscordError> {
+        debug!("Fetching the application of the bot");
+        let application: Application = self.request(Method::GET, "/applications/@me", None::<&()>).await?;
+        Ok(application.id)
+    }
+
+    /// List the custom emojis of the specified `owner`.
+    pub async fn list_emojis(&self, owner: &EmojiOwner) -> Result<Vec<Emoji>, DiscordError> {
+        debug!("Listing emojis of {owner:?}");
+        let path = owner.path();
+        match owner {
+            EmojiOwner::Application(_) => Ok(self.request::<ApplicationEmojis, ()>(Method::GET, &path, None).await?.items),
+            EmojiOwner::Guild(_) => self.request(Method::GET, &path, None::<&()>).await,
+        }
+    }
+
+    /// Upload a new custom emoji named `name` to the specified `owner`, from an `image` of the given `mime` type.
+    pub async fn create_emoji(&self, owner: &EmojiOwner, name: &str, mime: &str, image: &[u8]) -> Result<Emoji, DiscordError> {
+        use base64::Engine;
+
+        debug!("Uploading emoji {name} to {owner:?}");
+        let image = format!("data:{mime};base64,{}", base64::engine::general_purpose::STANDARD.encode(image));
+        self.request(Method::POST, &owner.path(), Some(&NewEmoji { name, image })).await
+    }
+}
+
+/// The subset of the message object returned by webhooks the bot needs.
+#[derive(Clone, Debug, Deserialize)]
+struct WebhookMessage {
+    id: String,
+}
+
+/// Split a webhook URL in the URL of the webhook itself and its query string, such as `?thread_id=...`.
+fn split_query(url: &str) -> (&str, &str) {
+    match url.find('?') {
+        Some(index) => url.split_at(index),
+        None => (url, ""),
+    }
+}
+
+/// Fail with [DiscordError::Status] if the response has an error status.
+async fn check_status(resp: reqwest::Response) -> Result<reqwest::Response, DiscordError> {
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(DiscordError::Status { status, body });
+    }
+    Ok(resp)
+}
+
+/// Send a message through the webhook at `url`, waiting for Discord to confirm it was posted, and return the ID of the posted message.
+pub async fn execute_webhook(http: &reqwest::Client, url: &str, message: &webhook::models::Message) -> Result<String, DiscordError> {
+    debug!("Executing webhook and waiting for the message ID...");
+    let resp = http.post(url).query(&[("wait", "true")]).json(message).send().await?;
+    let posted: WebhookMessage = check_status(resp).await?.json().await?;
+    Ok(posted.id)
+}
+
+/// Replace the message having the specified `message_id`, previously sent through the webhook at `url`.
+pub async fn edit_webhook_message(http: &reqwest::Client, url: &str, message_id: &str, message: &webhook::models::Message) -> Result<(), DiscordError> {
+    debug!("Editing webhook message {message_id}...");
+    let (base, query) = split_query(url);
+    let resp = http.patch(format!("{base}/messages/{message_id}{query}")).json(message).send().await?;
+    check_status(resp).await?;
+    Ok(())
+}
@@ -0,0 +1,157 @@
+//! This module is about mapping Dota entities to the Discord emojis representing them.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use serde::Deserialize;
+use crate::stratz;
+
+/// The bundled mapping of hero IDs to the emojis of the original Revenant's Brooch Discord server.
+const BUNDLED_HEROES: &str = include_str!("heroes.toml");
+
+/// No item emojis are bundled, so items are displayed by name unless a table is configured.
+const BUNDLED_ITEMS: &str = "";
+
+/// No medal emojis are bundled, so medals are displayed by name unless a table is configured.
+const BUNDLED_MEDALS: &str = "";
+
+/// Error enumeration for possible emoji table errors.
+#[derive(Debug, thiserror::Error)]
+pub enum EmojiError {
+    /// The emoji table file could not be read.
+    #[error("could not read emoji table {path:?}: {source}")]
+    Read {
+        /// The path of the emoji table file.
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The emoji table is not valid TOML, or is not a table of strings.
+    #[error("could not parse emoji table: {0}")]
+    Parse(#[from] toml::de::Error),
+    /// A key of the emoji table is not a valid ID.
+    #[error("emoji table key {0:?} is not a valid ID")]
+    Id(String),
+}
+
+/// A table mapping the IDs of some kind of Dota entity to Discord emojis, parsed from TOML `id = "emoji"` pairs.
+#[derive(Clone, Debug, Default)]
+pub struct EmojiTable {
+    emojis: HashMap<i64, String>,
+}
+
+impl EmojiTable {
+    /// Parse the table from the contents of a TOML file.
+    pub fn parse(contents: &str) -> Result<Self, EmojiError> {
+        let raw: HashMap<String, String> = toml::from_str(contents)?;
+        let emojis = raw.into_iter()
+            .map(|(id, emoji)| id.parse::<i64>().map(|id| (id, emoji)).map_err(|_| EmojiError::Id(id)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { emojis })
+    }
+
+    /// Load the table from the TOML file at the given `path`, or parse the `bundled` one if no path is given.
+    pub fn load(path: Option<&str>, bundled: &str) -> Result<Self, EmojiError> {
+        match path {
+            Some(path) => {
+                trace!("Reading emoji table from {path:?}...");
+                let contents = std::fs::read_to_string(path).map_err(|source| EmojiError::Read { path: path.into(), source })?;
+                Self::parse(&contents)
+            }
+            None => Self::parse(bundled),
+        }
+    }
+
+    /// Get the emoji of the entity having the specified `id`, if any.
+    pub fn get(&self, id: i64) -> Option<&str> {
+        self.emojis.get(&id).map(|emoji| emoji.as_str())
+    }
+
+    /// Set the emoji of the entity having the specified `id`.
+    pub fn insert(&mut self, id: i64, emoji: String) {
+        self.emojis.insert(id, emoji);
+    }
+
+    /// Serialize the table to TOML `id = "emoji"` pairs, sorted by ID.
+    pub fn to_toml(&self) -> String {
+        let mut ids: Vec<&i64> = self.emojis.keys().collect();
+        ids.sort();
+        ids.into_iter()
+            .map(|id| format!("{id} = {}\n", toml::Value::String(self.emojis[id].clone())))
+            .collect()
+    }
+}
+
+/// Configuration of the emoji tables, each being the path of a TOML file of `id = "emoji"` pairs.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmojiConfig {
+    /// The table of hero emojis; if not set, the bundled one is used.
+    pub heroes: Option<String>,
+    /// The table of item emojis; if not set, items are displayed by name.
+    pub items: Option<String>,
+    /// The table of rank medal emojis, keyed by rank such as `54` for Legend 4, or by medal such as `50` for all Legend ranks; if not set, medals are displayed by name.
+    pub medals: Option<String>,
+}
+
+/// All the emoji tables used in announcements.
+#[derive(Clone, Debug, Default)]
+pub struct Emojis {
+    /// Emojis of heroes, keyed by hero ID.
+    pub heroes: EmojiTable,
+    /// Emojis of items, keyed by item ID.
+    pub items: EmojiTable,
+    /// Names of items, keyed by item ID, displayed for the items without an emoji.
+    pub item_names: HashMap<i64, String>,
+    /// Emojis of rank medals, keyed by rank.
+    pub medals: EmojiTable,
+}
+
+impl Emojis {
+    /// Load all the emoji tables specified in the given configuration.
+    pub fn load(config: &EmojiConfig) -> Result<Self, EmojiError> {
+        Ok(Self {
+            heroes: EmojiTable::load(config.heroes.as_deref(), BUNDLED_HEROES)?,
+            items: EmojiTable::load(config.items.as_deref(), BUNDLED_ITEMS)?,
+            item_names: HashMap::new(),
+            medals: EmojiTable::load(config.medals.as_deref(), BUNDLED_MEDALS)?,
+        })
+    }
+
+    /// Set the names of the items without an emoji from the given item constants.
+    pub fn set_item_names(&mut self, items: &[stratz::ItemConstant]) {
+        self.item_names = items.iter()
+            .filter_map(|item| Some((item.id?.into(), item.display_name.clone().or_else(|| item.short_name.clone())?)))
+            .collect();
+    }
+
+    /// Get the emoji of the item having the specified `id`, or its name if it has none.
+    pub fn item(&self, id: i64) -> String {
+        match (self.items.get(id), self.item_names.get(&id)) {
+            (Some(emoji), _) => emoji.to_string(),
+            (None, Some(name)) => name.clone(),
+            (None, None) => format!("#{id}"),
+        }
+    }
+
+    /// Get the emoji of the medal of the specified `rank`, falling back to the one of the medal regardless of its stars.
+    pub fn medal(&self, rank: i64) -> Option<&str> {
+        self.medals.get(rank).or_else(|| self.medals.get(rank / 10 * 10))
+    }
+}
+
+/// Warn about the heroes of the game which have no emoji in the hero table, and whose names will be displayed instead.
+pub fn report_missing_heroes(emojis: &Emojis, heroes: &[stratz::HeroConstant]) {
+    let missing: Vec<&stratz::HeroConstant> = heroes.iter()
+        .filter(|hero| hero.id.map(|id| emojis.heroes.get(id.into()).is_none()).unwrap_or(false))
+        .collect();
+    if missing.is_empty() {
+        debug!("All {} heroes have an emoji!", heroes.len());
+        return
+    }
+    for hero in missing {
+        warn!(
+            "Hero {} ({}) has no emoji, its name will be displayed instead.",
+            hero.id.unwrap_or_default(),
+            hero.display_name.as_deref().or(hero.short_name.as_deref()).unwrap_or("unknown"),
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! This module is about translating the strings displayed in announcements.
+//!
+//! Strings are looked up by message ID, such as `label.duration` or `game_mode.TURBO`, in the catalog of the configured locale, falling back to the English one.
+
+use std::collections::HashMap;
+use serde::Serialize;
+
+/// The bundled catalogs, keyed by locale.
+const BUNDLED: [(&str, &str); 2] = [
+    ("en", include_str!("locales/en.toml")),
+    ("it", include_str!("locales/it.toml")),
+];
+
+/// The locale used for the messages missing from the configured one.
+pub const FALLBACK_LOCALE: &str = "en";
+
+/// Error enumeration for possible catalog errors.
+#[derive(Debug, thiserror::Error)]
+pub enum LocaleError {
+    /// No catalog is bundled for the requested locale.
+    #[error("unknown locale {0:?}, available locales are: {}", available().join(", "))]
+    Unknown(String),
+    /// A bundled catalog is not valid TOML.
+    #[error("could not parse catalog: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+/// Get the locales catalogs are bundled for.
+pub fn available() -> Vec<&'static str> {
+    BUNDLED.iter().map(|(locale, _)| *locale).collect()
+}
+
+/// A catalog of translated strings, keyed by message ID.
+#[derive(Clone, Debug, Default)]
+pub struct Catalog {
+    messages: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// Parse a catalog from the contents of a TOML file, where nested tables form the dotted message IDs.
+    fn parse(contents: &str) -> Result<Self, LocaleError> {
+        let table: toml::Table = toml::from_str(contents)?;
+        let mut messages = HashMap::new();
+        flatten(&mut messages, "", &table);
+        Ok(Self { messages })
+    }
+
+    /// Load the bundled catalog of the given `locale`, filling in the messages it lacks from the [FALLBACK_LOCALE] one.
+    pub fn load(locale: &str) -> Result<Self, LocaleError> {
+        let bundled = |locale: &str| BUNDLED.iter().find(|(name, _)| *name == locale).map(|(_, contents)| *contents);
+        trace!("Loading {locale} catalog...");
+        let mut catalog = Self::parse(bundled(locale).ok_or_else(|| LocaleError::Unknown(locale.to_string()))?)?;
+        let fallback = Self::parse(bundled(FALLBACK_LOCALE).expect("fallback catalog to be bundled"))?;
+        for (id, message) in fallback.messages {
+            catalog.messages.entry(id).or_insert(message);
+        }
+        Ok(catalog)
+    }
+
+    /// Get the string having the given message `id`, or the ID itself if the catalog has no such message.
+    pub fn get(&self, id: &str) -> String {
+        match self.messages.get(id) {
+            Some(message) => message.clone(),
+            None => {
+                warn!("Missing translation for {id:?}");
+                id.to_string()
+            }
+        }
+    }
+
+    /// Get the string of an enum value in the given `group`, keyed by the serialized name of the value, or the `unknown` string of the group if missing.
+    pub fn get_enum<T: Serialize>(&self, group: &str, value: &T) -> String {
+        let name = serde_json::to_value(value).ok().and_then(|value| value.as_str().map(String::from));
+        name.and_then(|name| self.messages.get(&format!("{group}.{name}")).cloned())
+            .unwrap_or_else(|| self.get(&format!("{group}.unknown")))
+    }
+}
+
+/// Insert all the strings of the `table` into `messages`, prefixing their keys with `prefix`.
+fn flatten(messages: &mut HashMap<String, String>, prefix: &str, table: &toml::Table) {
+    for (key, value) in table {
+        let id = match prefix.is_empty() {
+            true => key.clone(),
+            false => format!("{prefix}.{key}"),
+        };
+        match value {
+            toml::Value::String(message) => { messages.insert(id, message.clone()); },
+            toml::Value::Table(table) => flatten(messages, &id, table),
+            _ => warn!("Ignoring catalog entry {id:?}, as it is not a string"),
+        }
+    }
+}
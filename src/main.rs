@@ -1,426 +1,550 @@
 extern crate pretty_env_logger;
 #[macro_use] extern crate log;
 
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::announce::{Announcement, GuildInfo};
+use crate::config::{Config, ParseMode};
+use crate::emoji::Emojis;
+use crate::locale::Catalog;
+use crate::state::{AwaitingParse, MemberRank, PendingAnnouncement, State, StateError, StateStore};
 use crate::stratz::StratzError;
+use crate::template::{Renderer, TemplateError};
 
+mod announce;
 mod config;
+mod discord;
+mod emoji;
+mod locale;
+mod ranks;
+mod routing;
+mod schedule;
+mod state;
 mod stratz;
-
-/// The period of time elapsed between two match scans.
-const MATCH_SCAN_PERIOD: tokio::time::Duration = tokio::time::Duration::from_secs(60 * 30);
-
-/// The amount of matches to request on every scan.
-const MATCH_SCAN_TAKE: i64 = 10;
-
-/// The minimum number of players that must be in a match for it to be announced.
-const MATCH_ANNOUNCE_PLAYERS: usize = 1;
+mod template;
+mod upload;
 
 #[tokio::main]
 async fn main() -> ! {
     pretty_env_logger::init();
     debug!("Logger initialized!");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("upload-emojis") {
+        trace!("Running upload-emojis subcommand...");
+        match upload::run(&args[1..]).await {
+            Ok(()) => std::process::exit(0),
+            Err(e) => {
+                error!("Could not upload emojis: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    trace!("Loading configuration...");
+    let config: Config = match Config::load() {
+        Ok(config) => config,
+        Err(errors) => {
+            for e in errors.iter() {
+                error!("Invalid configuration: {e}");
+            }
+            std::process::exit(1);
+        }
+    };
+
+    trace!("Creating state store...");
+    let store = state::JsonFileStore::new(&config.state_path);
+    trace!("Loading state...");
+    let state: Mutex<State> = match store.load() {
+        Ok(state) => Mutex::new(state),
+        Err(e) => {
+            error!("Could not load state: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    trace!("Loading emoji tables...");
+    let mut emojis: Emojis = match Emojis::load(&config.emojis) {
+        Ok(emojis) => emojis,
+        Err(e) => {
+            error!("Could not load emojis: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    trace!("Loading string catalog...");
+    let catalog: Catalog = match Catalog::load(&config.announce.locale) {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            error!("Could not load string catalog: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    trace!("Compiling announcement templates...");
+    let renderer: Renderer = match Renderer::new(&config.template, catalog) {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            error!("Invalid announcement template: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    trace!("Creating new Stratz client...");
+    let http = reqwest::Client::new();
+    let stratz_client = stratz::Client::new(http.clone(), stratz::DEFAULT_API_URL, config.stratz_jwt.clone(), config.retry.clone());
+
+    trace!("Checking for heroes without an emoji...");
+    match stratz_client.fetch_heroes().await {
+        Ok(heroes) => emoji::report_missing_heroes(&emojis, &heroes),
+        Err(e) => warn!("Could not check for heroes without an emoji: {e}"),
+    }
+    if config.announce.items {
+        trace!("Fetching item names...");
+        match stratz_client.fetch_items().await {
+            Ok(items) => emojis.set_item_names(&items),
+            Err(e) => warn!("Could not fetch item names, items without an emoji will be displayed by ID: {e}"),
+        }
+    }
+
+    let ctx = Context { config, stratz: stratz_client, store: Box::new(store), state, emojis, renderer, http };
+
     trace!("Entering main loop...");
-    let mut current_match_id: i64 = -1;
     loop {
         trace!("Starting iteration of the main loop...");
-        match match_scan(&mut current_match_id).await {
-            Ok(()) => debug!("Completed match scan successfully!"),
-            Err(e) => error!("Error in match scan: {:#?}", &e),
+        trace!("Scanning {} guilds concurrently...", ctx.config.guilds.len());
+        let scans = ctx.config.guilds.iter().map(|guild| match_scan(&ctx, guild.id));
+        let results = futures::future::join_all(scans).await;
+        for (guild_id, result) in ctx.config.guilds.iter().map(|guild| guild.id).zip(results) {
+            match result {
+                Ok(()) => debug!("Completed match scan of guild {guild_id} successfully!"),
+                Err(e) => error!("Error in match scan of guild {guild_id}: {e}"),
+            }
+        }
+        let mut delay = schedule::next_delay(&ctx.config.scan.schedule, ctx.config.scan.period, &chrono::Local::now());
+        if let Some(suggested) = ctx.stratz.rate_limit().and_then(|rate_limit| rate_limit.suggested_delay()) {
+            if suggested > delay {
+                warn!("Nearly out of Stratz requests, slowing down scans to every {suggested:?}");
+                delay = suggested;
+            }
         }
-        trace!("Sleeping in the main loop...");
-        tokio::time::sleep(MATCH_SCAN_PERIOD).await;
+        trace!("Sleeping in the main loop for {delay:?}...");
+        tokio::time::sleep(delay).await;
     }
 }
 
+/// Everything match scans need, shared between the scans of all guilds.
+struct Context {
+    config: Config,
+    stratz: stratz::Client,
+    store: Box<dyn StateStore + Sync>,
+    state: Mutex<State>,
+    emojis: Emojis,
+    renderer: Renderer,
+    http: reqwest::Client,
+}
 
-#[derive(Clone, Debug)]
+/// Error enumeration for possible errors while scanning and announcing matches.
+#[derive(Debug, thiserror::Error)]
 enum RefreshError {
-    Stratz(StratzError),
-    Data,
-    Discord,
+    /// The matches could not be fetched from Stratz.
+    #[error("could not fetch data from Stratz: {0}")]
+    Stratz(#[from] StratzError),
+    /// A field required to announce a match was missing from the Stratz response.
+    #[error("missing {path} in Stratz response{}", match .match_id { Some(id) => format!(" for match {id}"), None => String::new() })]
+    Missing {
+        /// The ID of the match the field belongs to, if known.
+        match_id: Option<i64>,
+        /// The GraphQL path of the missing field, such as `players[3].steamAccount.name`.
+        path: String,
+    },
+    /// The announcement of a match could not be rendered.
+    #[error("could not render announcement of match {match_id}: {source}")]
+    Template {
+        /// The ID of the match being announced.
+        match_id: i64,
+        source: TemplateError,
+    },
+    /// The announcement of a match could not be sent to a Discord webhook.
+    #[error("could not send announcement of match {match_id} to webhook {webhook}: {message}")]
+    Discord {
+        /// The ID of the match being announced.
+        match_id: i64,
+        /// The name of the webhook the announcement was being sent to.
+        webhook: String,
+        /// The error returned by the webhook client.
+        message: String,
+    },
+    /// The state could not be persisted.
+    #[error("could not persist state: {0}")]
+    State(#[from] StateError),
 }
 
+/// Extension trait turning missing values of Stratz responses into [RefreshError::Missing].
+trait Require<T> {
+    /// Unwrap the value, or return an error saying that the field at `path` of the match with the given `match_id` is missing.
+    fn require(self, match_id: Option<i64>, path: impl Into<String>) -> Result<T, RefreshError>;
+}
 
-async fn match_scan(current_match_id: &mut i64) -> Result<(), RefreshError> {
-    debug!("Starting match scan...");
+impl<T> Require<T> for Option<T> {
+    fn require(self, match_id: Option<i64>, path: impl Into<String>) -> Result<T, RefreshError> {
+        self.ok_or_else(|| RefreshError::Missing { match_id, path: path.into() })
+    }
+}
 
-    trace!("Creating new reqwest client...");
-    let http_client = reqwest::Client::new();
-    trace!("Creating new webhook client...");
-    let webhook_client = webhook::client::WebhookClient::new(&config::discord_webhook_url());
-    trace!("Fetching matches...");
-    let response = stratz::fetch_matches(http_client, config::followed_guild_id(), MATCH_SCAN_TAKE).await.map_err(|e| RefreshError::Stratz(e))?;
 
-    trace!("Ensuring there are no errors in the data...");
-    if let Some(errors) = response.errors {
-        error!("Errors in STRATZ response: {:#?}", errors);
-        return Err(RefreshError::Data);
-    }
+async fn match_scan(ctx: &Context, guild_id: i64) -> Result<(), RefreshError> {
+    debug!("Starting match scan of guild {guild_id}...");
+
+    trace!("Creating new webhook clients...");
+    let webhooks: Vec<(routing::Webhook, webhook::client::WebhookClient)> = ctx.config.guild_webhooks(guild_id).into_iter()
+        .map(|webhook| {
+            let client = webhook::client::WebhookClient::new(&webhook.url);
+            (webhook, client)
+        })
+        .collect();
+    trace!("Fetching matches...");
+    let last_match_id = ctx.state.lock().expect("state mutex to not be poisoned").last_match_id(guild_id);
+    let response = ctx.stratz.fetch_new_matches(guild_id, ctx.config.scan.take, last_match_id, ctx.config.scan.max_pages, ctx.config.announce.ranks).await?;
 
     trace!("Ensuring the data object exists...");
-    let data: stratz::ResponseData = response.data.ok_or_else(|| RefreshError::Data)?;
+    let data: stratz::ResponseData = response.data.require(None, "data")?;
     trace!("Ensuring the guild object exists...");
-    let guild: stratz::Guild = data.guild.ok_or_else(|| RefreshError::Data)?;
+    let guild: stratz::Guild = data.guild.require(None, "guild")?;
     trace!("Ensuring the guild id exists...");
-    let id: i64 = guild.id.ok_or_else(|| RefreshError::Data)?;
+    let id: i64 = guild.id.require(None, "guild.id")?;
     trace!("Ensuring the guild name exists...");
-    let name: String = guild.name.ok_or_else(|| RefreshError::Data)?;
+    let name: String = guild.name.require(None, "guild.name")?;
     trace!("Ensuring the guild logo exists...");
-    let logo: String = guild.logo.ok_or_else(|| RefreshError::Data)?;
+    let logo: String = guild.logo.require(None, "guild.logo")?;
+    let info = GuildInfo { id, name, logo };
+    let members: Vec<i64> = guild.members.iter().flatten().flatten().filter_map(|member| member.steam_account_id).collect();
+    trace!("Retrying pending announcements...");
+    retry_pending(ctx, &webhooks, &info).await?;
     trace!("Ensuring the matches object exists...");
-    let matches: Vec<Option<stratz::Match>> = guild.matches.ok_or_else(|| RefreshError::Data)?;
+    let matches: Vec<Option<stratz::Match>> = guild.matches.require(None, "guild.matches")?;
+    let full_matches = fetch_full_matches(ctx, &matches, last_match_id, guild_id).await;
+    trace!("Editing announcements awaiting parse...");
+    refresh_awaiting(ctx, &webhooks, &info, &full_matches).await?;
     trace!("Parsing matches from the last to the first...");
-    for match_ in matches.into_iter().rev() {
+    for (index, match_) in matches.into_iter().enumerate().rev() {
         trace!("Ensuring the match object exists...");
-        let match_ = match_.ok_or_else(|| RefreshError::Data)?;
-        match_announce(current_match_id, &webhook_client, match_, &id, &name, &logo).await?;
+        let Some(match_) = match_ else {
+            warn!("Skipping guild.matches[{index}] of guild {guild_id}, as it is missing from the Stratz response.");
+            continue
+        };
+        if match_.id.is_some_and(|id| id <= last_match_id) {
+            trace!("Skipping guild.matches[{index}] of guild {guild_id}, as it was already announced.");
+            continue
+        }
+        if ctx.config.announce.parse == ParseMode::Hold && announce::awaits_parse(&ctx.config.announce, &match_) {
+            debug!("Holding announcements of guild {guild_id} from guild.matches[{index}] on, as it is not parsed yet.");
+            break
+        }
+        let match_details = match (ctx.config.needs_details(), match_.id.and_then(|id| full_matches.get(&id))) {
+            (true, Some(full)) => stratz::MatchDetails::from_full_match(full),
+            _ => stratz::MatchDetails::default(),
+        };
+        if let Err(e) = match_announce(ctx, &webhooks, match_, match_details, &info).await {
+            error!("Skipping guild.matches[{index}] of guild {guild_id}: {e}");
+        }
+    }
+
+    if ctx.config.announce.ranks {
+        trace!("Tracking ranks of guild members...");
+        rank_scan(ctx, &webhooks, &info, &members).await?;
     }
 
     Ok(())
 }
 
-#[derive(Clone, Debug)]
-enum MatchResult {
-    None,
-    Victory,
-    Defeat,
-    Both,
+/// Fetch in a single request all the matches of the guild having the specified `guild_id` whose full data is needed: the `matches` more recent than `last_match_id`, if any announcement option needs their details, and the ones whose announcements await parse.
+///
+/// If the request fails, the matches are announced without their details.
+async fn fetch_full_matches(ctx: &Context, matches: &[Option<stratz::Match>], last_match_id: i64, guild_id: i64) -> HashMap<i64, stratz::Match> {
+    let mut ids: Vec<i64> = Vec::new();
+    if ctx.config.needs_details() {
+        ids.extend(matches.iter()
+            .filter_map(|match_| match_.as_ref().and_then(|match_| match_.id))
+            .filter(|id| *id > last_match_id));
+    }
+    ids.extend(ctx.state.lock().expect("state mutex to not be poisoned").awaiting_parse.iter()
+        .filter(|awaiting| awaiting.guild_id == guild_id)
+        .filter_map(|awaiting| awaiting.match_.id));
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.is_empty() {
+        trace!("No full matches are needed, not fetching them.");
+        return HashMap::new()
+    }
+    match ctx.stratz.fetch_full_matches(&ids).await {
+        Ok(full_matches) => full_matches,
+        Err(e) => {
+            warn!("Could not fetch full matches, announcing matches without their details: {e}");
+            HashMap::new()
+        }
+    }
 }
 
-async fn match_announce(current_match_id: &mut i64, client: &webhook::client::WebhookClient, match_: stratz::Match, guild_id: &i64, guild_name: &str, guild_logo: &str) -> Result<(), RefreshError> {
+/// Announce a match on all the `webhooks` accepting it, if it was not announced yet.
+///
+/// The match is marked as announced only after it has been either sent or queued for retrying on every webhook.
+async fn match_announce(ctx: &Context, webhooks: &[(routing::Webhook, webhook::client::WebhookClient)], match_: stratz::Match, details: stratz::MatchDetails, guild: &GuildInfo) -> Result<(), RefreshError> {
     trace!("Ensuring the match ID exists...");
-    let id: i64 = match_.id.ok_or_else(|| RefreshError::Data)?;
+    let id: i64 = match_.id.require(None, "match.id")?;
 
     trace!("Checking if the match should be announced...");
-    if id <= *current_match_id {
+    if id <= ctx.state.lock().expect("state mutex to not be poisoned").last_match_id(guild.id) {
         trace!("Skipping announcement of {id}, as it was already announced.");
         return Ok(())
     }
-    trace!("Bumping current match id up to the current value...");
-    *current_match_id = id;
 
-    trace!("Ensuring the player list exists...");
-    let players: Vec<Option<stratz::Player>> = match_.players.ok_or_else(|| RefreshError::Data)?;
+    let mut failed: Vec<String> = Vec::new();
+    let mut awaiting: Vec<AwaitingParse> = Vec::new();
+    match announce::build(&ctx.config, &ctx.emojis, &ctx.renderer, match_.clone(), &details, guild)? {
+        None => trace!("Skipping announcement of {id}, as it should not be announced."),
+        Some(announcement) => {
+            debug!("Announcing match {id}!");
+            for (webhook, client) in webhooks {
+                if !announcement.accepted_by(webhook) {
+                    trace!("Not sending announcement of {id} to webhook {}, as it is filtered out.", &webhook.name);
+                    continue
+                }
+                if ctx.config.announce.parse == ParseMode::Edit && announce::awaits_parse(&ctx.config.announce, &match_) {
+                    match send_awaiting(ctx, webhook, &announcement).await {
+                        Ok(message_id) => awaiting.push(AwaitingParse { guild_id: guild.id, webhook: webhook.name.clone(), message_id, match_: match_.clone() }),
+                        Err(e) => {
+                            warn!("{e}, queueing it for retrying.");
+                            failed.push(webhook.name.clone());
+                        }
+                    }
+                }
+                else if let Err(e) = send(webhook, client, &announcement).await {
+                    warn!("{e}, queueing it for retrying.");
+                    failed.push(webhook.name.clone());
+                }
+            }
+        }
+    }
 
-    if players.len() < MATCH_ANNOUNCE_PLAYERS {
-        trace!("Skipping announcement of {id}, as it does not have enough players.");
+    trace!("Marking match as announced...");
+    let mut state = ctx.state.lock().expect("state mutex to not be poisoned");
+    let failed = failed.into_iter().map(|webhook| PendingAnnouncement::new(guild.id, webhook, match_.clone(), details.clone(), ctx.config.announce.parse)).collect();
+    state.mark_announced(guild.id, id, failed, awaiting);
+    trace!("Persisting state...");
+    ctx.store.save(&state)?;
+
+    Ok(())
+}
+
+/// Retry sending the announcements of the guild that previously failed to be sent, giving up on the ones that failed too many times.
+///
+/// Announcements of matches still awaiting parse in [ParseMode::Edit] are recorded once sent, so that they are edited like the ones sent on the first try.
+async fn retry_pending(ctx: &Context, webhooks: &[(routing::Webhook, webhook::client::WebhookClient)], guild: &GuildInfo) -> Result<(), RefreshError> {
+    let names: Vec<&str> = webhooks.iter().map(|(webhook, _)| webhook.name.as_str()).collect();
+    let pending: Vec<PendingAnnouncement> = ctx.state.lock().expect("state mutex to not be poisoned").retain_pending(guild.id, &names);
+    if pending.is_empty() {
+        trace!("No pending announcements to retry.");
         return Ok(())
     }
 
-    debug!("Announcing match {id}!");
-
-    trace!("Ensuring the lobby type exists...");
-    let lobby_type: stratz::LobbyType = match_.lobby_type.ok_or_else(|| RefreshError::Data)?;
-    trace!("Ensuring the game mode exists...");
-    let game_mode: stratz::GameMode = match_.game_mode.ok_or_else(|| RefreshError::Data)?;
-    trace!("Ensuring the duration exists...");
-    let duration = match_.duration_seconds.ok_or_else(|| RefreshError::Data)?;
-    let duration: chrono::Duration = chrono::Duration::seconds(duration);
-    trace!("Ensuring the end date time exists...");
-    let end = match_.end_date_time.ok_or_else(|| RefreshError::Data)?;
-    let end = i64::try_from(end).map_err(|_| RefreshError::Data)?;
-    let end = chrono::NaiveDateTime::from_timestamp_opt(end, 0).expect("timestamp to be valid");
-    let end = chrono::DateTime::<chrono::Utc>::from_utc(end, chrono::Utc);
-
-    trace!("Determining duration string...");
-    let mins = duration.num_seconds() / 60;
-    let secs = duration.num_seconds() % 60;
-    let duration_field = format!("{}:{:02}", &mins, &secs);
-
-    trace!("Determining match result...");
-    let mut is_victory: bool = false;
-    let mut is_defeat: bool = false;
-    for player in players.iter().cloned() {
-        trace!("Ensuring the player object exists...");
-        let player: stratz::Player = player.ok_or_else(|| RefreshError::Data)?;
-        trace!("Ensuring the victory property exists...");
-        let player_result: bool = player.is_victory.ok_or_else(|| RefreshError::Data)?;
-        trace!("Marking player's result...");
-        match player_result {
-            true => is_victory = true,
-            false => is_defeat = true,
-        }
-    }
-    let match_result = match (is_victory, is_defeat) {
-        (false, false) => MatchResult::None,
-        (true, false) => MatchResult::Victory,
-        (false, true) => MatchResult::Defeat,
-        (true, true) => MatchResult::Both,
-    };
-    trace!("Match result is: {match_result:?}");
-
-    // Ughhh, I'd really like to use map-reduce here...
-    trace!("Determining players' teams...");
-    let mut radiant_players: Vec<stratz::Player> = Vec::new();
-    let mut dire_players: Vec<stratz::Player> = Vec::new();
-    for player in players.iter().cloned() {
-        trace!("Ensuring the player object exists...");
-        let player: stratz::Player = player.ok_or_else(|| RefreshError::Data)?;
-        trace!("Ensuring the radiant property exists...");
-        let player_team: bool = player.is_radiant.ok_or_else(|| RefreshError::Data)?;
-        trace!("Marking player's team...");
-        match player_team {
-            true => radiant_players.push(player),
-            false => dire_players.push(player),
+    let mut still_pending: Vec<PendingAnnouncement> = Vec::new();
+    let mut awaiting: Vec<AwaitingParse> = Vec::new();
+    for pending in pending {
+        let (webhook, client) = webhooks.iter().find(|(webhook, _)| webhook.name == pending.webhook).expect("pending announcements to be for configured webhooks");
+        let announcement = match announce::build(&ctx.config, &ctx.emojis, &ctx.renderer, pending.match_.clone(), &pending.details, guild) {
+            Ok(Some(announcement)) => announcement,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Dropping pending announcement for webhook {}: {e}", &pending.webhook);
+                continue
+            }
+        };
+        debug!("Retrying announcement of {}, attempt {}...", announcement.match_id, pending.attempts + 1);
+        let result = match pending.parse == ParseMode::Edit && announce::awaits_parse(&ctx.config.announce, &pending.match_) {
+            true => send_awaiting(ctx, webhook, &announcement).await
+                .map(|message_id| Some(AwaitingParse { guild_id: guild.id, webhook: webhook.name.clone(), message_id, match_: pending.match_.clone() })),
+            false => send(webhook, client, &announcement).await.map(|()| None),
+        };
+        match result {
+            Ok(sent) => {
+                debug!("Sent pending announcement of {} successfully!", announcement.match_id);
+                awaiting.extend(sent);
+            }
+            Err(e) => match pending.failed(ctx.config.announce.max_attempts) {
+                Some(pending) => {
+                    warn!("{e}, will retry on the next scan.");
+                    still_pending.push(pending);
+                }
+                None => error!("{e}, giving up after {} attempts.", ctx.config.announce.max_attempts),
+            },
         }
     }
-    trace!("Teams determined successfully!");
-
-    // Ughhh, here as well...
-    trace!("Creating Radiant's players field...");
-    let mut radiant_field = String::new();
-    for player in radiant_players {
-        let line = render_player(player)?;
-        radiant_field.push_str(&line);
-        radiant_field.push('\n');
-    }
-    trace!("Creating Dire's players field...");
-    let mut dire_field = String::new();
-    for player in dire_players {
-        let line = render_player(player)?;
-        dire_field.push_str(&line);
-        dire_field.push('\n');
-    }
 
-    debug!("Sending match announcement...");
-    client.send(|mut msg| {
-        msg = msg.content(&*format!("https://stratz.com/matches/{}", &id));
-        msg = msg.embed(|mut embed| {
-            embed = embed.author(
-                guild_name,
-                Some(format!("https://stratz.com/guilds/{}", &guild_id)),
-                Some(format!("https://steamusercontent-a.akamaihd.net/ugc/{}/", &guild_logo)),
-            );
-            embed = embed.title(&*format!(
-                "{} · {} · {}",
-                &*match match_result {
-                    MatchResult::None => "Cancelled",
-                    MatchResult::Victory => "Victory",
-                    MatchResult::Defeat => "Defeat",
-                    MatchResult::Both => "Clash",
-                },
-                &*match lobby_type {
-                    stratz::LobbyType::UNRANKED => "Unranked",
-                    stratz::LobbyType::PRACTICE => "Lobby",
-                    stratz::LobbyType::TOURNAMENT => "The International",
-                    stratz::LobbyType::TUTORIAL => "Tutorial",
-                    stratz::LobbyType::COOP_VS_BOTS => "Bots",
-                    stratz::LobbyType::TEAM_MATCH => "Guild",
-                    stratz::LobbyType::SOLO_QUEUE => "Solo Ranked",
-                    stratz::LobbyType::RANKED => "Ranked",
-                    stratz::LobbyType::SOLO_MID => "Duel",
-                    stratz::LobbyType::BATTLE_CUP => "Battle Cup",
-                    stratz::LobbyType::EVENT => "Event",
-                    _ => "Unknown",
-                },
-                &*match game_mode {
-                    stratz::GameMode::NONE => "None",
-                    stratz::GameMode::ALL_PICK => "All Pick",
-                    stratz::GameMode::CAPTAINS_MODE => "Captains Mode",
-                    stratz::GameMode::RANDOM_DRAFT => "Random Draft",
-                    stratz::GameMode::SINGLE_DRAFT => "Single Draft",
-                    stratz::GameMode::ALL_RANDOM => "All Random",
-                    stratz::GameMode::INTRO => "Intro",
-                    stratz::GameMode::THE_DIRETIDE => "Diretide",
-                    stratz::GameMode::REVERSE_CAPTAINS_MODE => "Reverse Captains Mode",
-                    stratz::GameMode::THE_GREEVILING => "Greeviling",
-                    stratz::GameMode::TUTORIAL => "Tutorial",
-                    stratz::GameMode::MID_ONLY => "Mid Only",
-                    stratz::GameMode::LEAST_PLAYED => "Least Played",
-                    stratz::GameMode::NEW_PLAYER_POOL => "Limited Heroes",
-                    stratz::GameMode::COMPENDIUM_MATCHMAKING => "Compendium",
-                    stratz::GameMode::CUSTOM => "Custom",
-                    stratz::GameMode::CAPTAINS_DRAFT => "Captains Draft",
-                    stratz::GameMode::BALANCED_DRAFT => "Balanced Draft",
-                    stratz::GameMode::ABILITY_DRAFT => "Ability Draft",
-                    stratz::GameMode::EVENT => "Event",
-                    stratz::GameMode::ALL_RANDOM_DEATH_MATCH => "All Random Deathmatch",
-                    stratz::GameMode::SOLO_MID => "Solo Mid",
-                    stratz::GameMode::ALL_PICK_RANKED => "All Draft",
-                    stratz::GameMode::TURBO => "Turbo",
-                    stratz::GameMode::MUTATION => "Mutation",
-                    _ => "Unknown",
-                }
-            ));
-            embed = embed.color(&*match match_result {
-                MatchResult::None => format!("{}", 0xA1A1A1),
-                MatchResult::Victory => format!("{}", 0x2ACB4F),
-                MatchResult::Defeat => format!("{}", 0xEC041F),
-                MatchResult::Both => format!("{}", 0xA1A1A1),
-            });
-
-            if radiant_field.len() > 0 {
-                embed = embed.field("<:radiant:958274781919207505> Radiant", &radiant_field, true);
-            }
-            if dire_field.len() > 0 {
-                embed = embed.field("<:dire:958274694203719740> Dire", &dire_field, true);
-            }
+    trace!("Updating pending announcements...");
+    let mut state = ctx.state.lock().expect("state mutex to not be poisoned");
+    state.replace_pending(guild.id, still_pending);
+    state.awaiting_parse.extend(awaiting);
+    trace!("Persisting state...");
+    ctx.store.save(&state)?;
 
-            embed = embed.field(":clock3: Duration", &duration_field, false);
+    Ok(())
+}
 
-            // embed = embed.footer("Powered by STRATZ", Some(String::from("https://cdn.discordapp.com/icons/268890221943324677/12b63c55a83a715ec569e91e40641db0.webp?size=96")));
-            embed = embed.timestamp(&end.to_rfc3339());
+/// Edit the announcements of the guild sent before their matches were parsed, using the `full_matches` fetched from Stratz.
+///
+/// Announcements are edited once their matches are parsed, and forgotten once [config::AnnounceConfig::parse_timeout] expires.
+async fn refresh_awaiting(ctx: &Context, webhooks: &[(routing::Webhook, webhook::client::WebhookClient)], guild: &GuildInfo, full_matches: &HashMap<i64, stratz::Match>) -> Result<(), RefreshError> {
+    let awaiting: Vec<AwaitingParse> = ctx.state.lock().expect("state mutex to not be poisoned").awaiting_parse.iter()
+        .filter(|awaiting| awaiting.guild_id == guild.id)
+        .cloned()
+        .collect();
+    if awaiting.is_empty() {
+        trace!("No announcements awaiting parse.");
+        return Ok(())
+    }
 
-            return embed;
+    let mut still_awaiting: Vec<AwaitingParse> = Vec::new();
+    for awaiting in awaiting {
+        let id = awaiting.match_.id.unwrap_or_default();
+        let Some((webhook, _)) = webhooks.iter().find(|(webhook, _)| webhook.name == awaiting.webhook) else {
+            warn!("Not editing announcement of {id} on webhook {}, as it is not configured anymore.", &awaiting.webhook);
+            continue
+        };
+        let Some(full) = full_matches.get(&id).filter(|full| full.parsed_date_time.is_some()) else {
+            if announce::awaits_parse(&ctx.config.announce, &awaiting.match_) {
+                trace!("Match {id} is not parsed yet, will check again on the next scan.");
+                still_awaiting.push(awaiting);
+            }
+            else {
+                info!("Match {id} was not parsed in time, leaving its announcement as it is.");
+            }
+            continue
+        };
+
+        trace!("Merging the parsed players of {id} with the announced ones...");
+        let members: Vec<i64> = awaiting.match_.players.iter().flatten().flatten().filter_map(|player| player.steam_account_id).collect();
+        let mut parsed = full.clone();
+        parsed.players = full.players.as_ref().map(|players| {
+            players.iter()
+                .filter(|player| player.as_ref().and_then(|player| player.steam_account_id).map(|id| members.contains(&id)).unwrap_or(false))
+                .cloned()
+                .collect()
         });
-        return msg;
-    }).await.map_err(|_| RefreshError::Discord)?;
+        let details = match ctx.config.needs_details() {
+            true => stratz::MatchDetails::from_full_match(full),
+            false => stratz::MatchDetails::default(),
+        };
+
+        let announcement = match announce::build(&ctx.config, &ctx.emojis, &ctx.renderer, parsed, &details, guild) {
+            Ok(Some(announcement)) => announcement,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Not editing announcement of {id} on webhook {}: {e}", &webhook.name);
+                continue
+            }
+        };
+        debug!("Editing announcement of {id} on webhook {}, as it was parsed...", &webhook.name);
+        if let Err(e) = discord::edit_webhook_message(&ctx.http, &webhook.url, &awaiting.message_id, &announcement.message).await {
+            warn!("Could not edit announcement of {id} on webhook {}, will retry on the next scan: {e}", &webhook.name);
+            still_awaiting.push(awaiting);
+        }
+    }
+
+    trace!("Updating announcements awaiting parse...");
+    let mut state = ctx.state.lock().expect("state mutex to not be poisoned");
+    state.awaiting_parse.retain(|awaiting| awaiting.guild_id != guild.id);
+    state.awaiting_parse.extend(still_awaiting);
+    trace!("Persisting state...");
+    ctx.store.save(&state)?;
 
     Ok(())
 }
 
-fn render_player(player: stratz::Player) -> Result<String, RefreshError> {
-    trace!("Ensuring the player's Steam account exists...");
-    let steam: stratz::Steam = player.steam_account.ok_or_else(|| RefreshError::Data)?;
-    trace!("Ensuring the player's name exists...");
-    let name: String = steam.name.ok_or_else(|| RefreshError::Data)?;
-    trace!("Ensuring the player's hero exists...");
-    let hero: stratz::Hero = player.hero.ok_or_else(|| RefreshError::Data)?;
-    trace!("Ensuring the player's hero ID exists...");
-    let hero_id: i16 = hero.id.ok_or_else(|| RefreshError::Data)?;
-    trace!("Ensuring the player's kill number exists...");
-    let kills: u8 = player.kills.ok_or_else(|| RefreshError::Data)?;
-    trace!("Ensuring the player's kill number exists...");
-    let deaths: u8 = player.deaths.ok_or_else(|| RefreshError::Data)?;
-    trace!("Ensuring the player's kill number exists...");
-    let assists: u8 = player.assists.ok_or_else(|| RefreshError::Data)?;
-
-    trace!("Matching hero ID to a Discord emoji...");
-    let emoji = match hero_id {
-        1 => "<:antimage:958248644652458005>",
-        2 => "<:axe:958248644547608586>",
-        3 => "<:bane:958249951480123394>",
-        4 => "<:bloodseeker:958248644585332796>",
-        5 => "<:crystal_maiden:958248644606320680>",
-        6 => "<:drow_ranger:958248644799238194>",
-        7 => "<:earthshaker:958248644748922900>",
-        8 => "<:juggernaut:958248644853760052>",
-        9 => "<:mirana:958248645038325771>",
-        10 => "<:morphling:958248645025759282>",
-        11 => "<:shadow_fiend:958248645147385866>",
-        12 => "<:phantom_lancer:958249951857610772>",
-        13 => "<:puck:958248645013147648>",
-        14 => "<:pudge:958248645088645160>",
-        15 => "<:razor:958248645134794762>",
-        16 => "<:sand_king:958248645113815080>",
-        17 => "<:storm_spirit:958249951262031934>",
-        18 => "<:sven:958249951467548682>",
-        19 => "<:tiny:958249951681450035>",
-        20 => "<:vengeful_spirit:958249951710826516>",
-        21 => "<:windranger:958249951652106310>",
-        22 => "<:zeus:958249951459168288>",
-        23 => "<:kunkka:958248645059313694>",
-        25 => "<:lina:958248645000560660>",
-        26 => "<:lion:958248644971229194>",
-        27 => "<:shadow_shaman:958248645193502771>",
-        28 => "<:slardar:958248645214486578>",
-        29 => "<:tidehunter:958249951228469269>",
-        30 => "<:witch_doctor:958249951715004446>",
-        31 => "<:lich:958248644992172032>",
-        32 => "<:riki:958248645138980914>",
-        33 => "<:enigma:958248644954456094>",
-        34 => "<:tinker:958249951480127518>",
-        35 => "<:sniper:958248645155762196>",
-        36 => "<:necrophos:958248644698595379>",
-        37 => "<:warlock:958249951740182569>",
-        38 => "<:beastmaster:958248644581146644>",
-        39 => "<:queen_of_pain:958248644736331829>",
-        40 => "<:venomancer:958249951580815400>",
-        41 => "<:faceless_void:958248644912484382>",
-        42 => "<:wraith_king:958248645239664700>",
-        43 => "<:death_prophet:958248644740517910>",
-        44 => "<:phantom_assassin:958249951941500938>",
-        45 => "<:pugna:958248644937662465>",
-        46 => "<:templar_assassin:958249952050544691>",
-        47 => "<:viper:958249951207497769>",
-        48 => "<:luna:958249951966674995>",
-        49 => "<:dragon_knight:958248644803436544>",
-        50 => "<:dazzle:958248644476301324>",
-        51 => "<:clockwerk:958248645210284032>",
-        52 => "<:leshrac:958248644912504883>",
-        53 => "<:natures_prophet:958248644560162888>",
-        54 => "<:lifestealer:958248645084467240>",
-        55 => "<:dark_seer:958248644644073502>",
-        56 => "<:clinkz:958249951735980042>",
-        57 => "<:omniknight:958248645080252426>",
-        58 => "<:enchantress:958248644853764097>",
-        59 => "<:huskar:958248644967022642>",
-        60 => "<:night_stalker:958248645004767282>",
-        61 => "<:broodmother:958248644702777364>",
-        62 => "<:bounty_hunter:958248644627271690>",
-        63 => "<:weaver:958249951429812266>",
-        64 => "<:jakiro:958249951568220190>",
-        65 => "<:batrider:958248644560191589>",
-        66 => "<:chen:958248644644057149>",
-        67 => "<:spectre:958248645235474473>",
-        69 => "<:doom:958248644698591232>",
-        68 => "<:ancient_apparition:958248644572762153>",
-        70 => "<:ursa:958249951845027860>",
-        71 => "<:spirit_breaker:958249951492730900>",
-        72 => "<:gyrocopter:958249951983456276>",
-        73 => "<:alchemist:958248644719558716>",
-        74 => "<:invoker:958249951429800009>",
-        75 => "<:silencer:958248645143199774>",
-        76 => "<:outworld_destroyer:958249951702441994>",
-        77 => "<:lycan:958249951958290432>",
-        78 => "<:brewmaster:958249951840854026>",
-        79 => "<:shadow_demon:958249951454982187>",
-        80 => "<:lone_druid:958249951798886400>",
-        81 => "<:chaos_knight:958249951840845894>",
-        82 => "<:meepo:958249952218345482>",
-        83 => "<:treant_protector:958249951626924073>",
-        84 => "<:ogre_magi:958249952000233472>",
-        85 => "<:undying:958249951987634176>",
-        86 => "<:rubick:958249951895388192>",
-        87 => "<:disruptor:958249952256086046>",
-        88 => "<:nyx_assassin:958249952130240562>",
-        89 => "<:naga_siren:958249952100904990>",
-        90 => "<:keeper_of_the_light:958249952105095218>",
-        91 => "<:io:958249952054759424>",
-        92 => "<:visage:958249952113459321>",
-        93 => "<:slark:958249952218325002>",
-        94 => "<:medusa:958249952193155092>",
-        95 => "<:troll_warlord:958249952201564210>",
-        96 => "<:centaur_warrunner:958249952184782848>",
-        97 => "<:magnus:958249952226738196>",
-        98 => "<:timbersaw:958249952251904050>",
-        99 => "<:bristleback:958251187243745280>",
-        100 => "<:tusk:958251186950111253>",
-        101 => "<:skywrath_mage:958251187260502036>",
-        102 => "<:abaddon:958251187180806146>",
-        103 => "<:elder_titan:958251187289878598>",
-        104 => "<:legion_commander:958251187117908018>",
-        105 => "<:techies:958251187222740992>",
-        106 => "<:ember_spirit:958251187143065610>",
-        107 => "<:earth_spirit:958251187172438046>",
-        108 => "<:underlord:958251187369549844>",
-        109 => "<:terrorblade:958251187382153226>",
-        110 => "<:phoenix:958251187214381096>",
-        111 => "<:oracle:958251187306627072>",
-        112 => "<:winter_wyvern:958251187281489980>",
-        113 => "<:arc_warden:958251187340197898>",
-        114 => "<:monkey_king:958251187205992469>",
-        119 => "<:dark_willow:958251187591868446>",
-        120 => "<:pangolier:958251187470233631>",
-        121 => "<:grimstroke:958251187709304862>",
-        123 => "<:hoodwink:958251187856105532>",
-        126 => "<:void_spirit:958251187772215386>",
-        128 => "<:snapfire:958251188023873587>",
-        129 => "<:mars:958251187696726016>",
-        135 => "<:dawnbreaker:958251187608645633>",
-        136 => "<:marci:958254609397334026>",
-        137 => "<:primal_beast:958254609397342258>",
-        138 => "<:muerta:1106405477186801674>",
-        _ => ":grey_question:",
-    };
+/// Send an announcement to a single webhook, returning the ID of the posted message so that it can be edited later.
+async fn send_awaiting(ctx: &Context, webhook: &routing::Webhook, announcement: &Announcement) -> Result<String, RefreshError> {
+    debug!("Sending announcement of {} to webhook {}, to be edited once parsed...", announcement.match_id, &webhook.name);
+    discord::execute_webhook(&ctx.http, &webhook.url, &announcement.message).await.map_err(|e| RefreshError::Discord {
+        match_id: announcement.match_id,
+        webhook: webhook.name.clone(),
+        message: e.to_string(),
+    })
+}
 
-    if let Some(imp) = player.imp {
-        trace!("IMP is available, displaying it...");
-        Ok(format!("{} {} [{}/{}/{}] `{:+}`", &emoji, &name, &kills, &deaths, &assists, &imp))
+/// Fetch the current ranks of the guild `members`, announcing the changes since the previous scan on the webhooks accepting them.
+///
+/// The first time the rank of a member is fetched, it is only stored.
+/// The rank of a member is updated only once its change was sent to every webhook accepting it, so changes that failed to be sent are announced again on the next scan.
+async fn rank_scan(ctx: &Context, webhooks: &[(routing::Webhook, webhook::client::WebhookClient)], guild: &GuildInfo, members: &[i64]) -> Result<(), RefreshError> {
+    if members.is_empty() {
+        trace!("Guild {} has no members, not fetching their ranks.", guild.id);
+        return Ok(())
     }
-    else {
-        trace!("IMP is not available, ignoring it...");
-        Ok(format!("{} {} [{}/{}/{}]", &emoji, &name, &kills, &deaths, &assists))
+    let players = match ctx.stratz.fetch_player_ranks(members).await {
+        Ok(players) => players,
+        Err(e) => {
+            warn!("Could not fetch ranks of the members of guild {}, will retry on the next scan: {e}", guild.id);
+            return Ok(())
+        }
+    };
+
+    let known: HashMap<i64, MemberRank> = ctx.state.lock().expect("state mutex to not be poisoned").ranks.get(&guild.id).cloned().unwrap_or_default();
+    let mut current: HashMap<i64, MemberRank> = HashMap::new();
+    for player in players {
+        trace!("Ensuring the steam account id exists...");
+        let Some(steam_account_id) = player.steam_account_id else {
+            warn!("Ignoring rank of a member of guild {}, as their Steam account ID is missing from the Stratz response.", guild.id);
+            continue
+        };
+        let rank = ranks::current_rank(&player);
+        let Some(old) = known.get(&steam_account_id) else {
+            trace!("Storing rank of {steam_account_id} for the first time, not announcing it.");
+            current.insert(steam_account_id, rank);
+            continue
+        };
+        let rank = ranks::merge_rank(old, rank);
+        current.insert(steam_account_id, rank);
+        let message = match ranks::build(&ctx.emojis, &ctx.renderer, guild, &player, old, &rank) {
+            Ok(Some(message)) => message,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Not announcing rank change of {steam_account_id}: {e}");
+                continue
+            }
+        };
+        for (webhook, client) in webhooks.iter() {
+            if !webhook.rank_changes {
+                trace!("Not sending rank change of {steam_account_id} to webhook {}, as it does not accept rank changes.", &webhook.name);
+                continue
+            }
+            debug!("Sending rank change of {steam_account_id} to webhook {}...", &webhook.name);
+            if let Err(e) = client.send_message(&message).await {
+                warn!("Could not send rank change of {steam_account_id} to webhook {}, will retry on the next scan: {e}", &webhook.name);
+                // Keeping the old rank makes the next scan detect the change again.
+                current.remove(&steam_account_id);
+            }
+        }
     }
-}
\ No newline at end of file
+
+    trace!("Updating known ranks...");
+    let mut state = ctx.state.lock().expect("state mutex to not be poisoned");
+    // Members whose rank could not be fetched this time keep their last known one, while the ones who left the guild are forgotten.
+    let known = state.ranks.entry(guild.id).or_default();
+    known.retain(|steam_account_id, _| members.contains(steam_account_id));
+    known.extend(current);
+    trace!("Persisting state...");
+    ctx.store.save(&state)?;
+
+    Ok(())
+}
+
+/// Send an announcement to a single webhook.
+async fn send(webhook: &routing::Webhook, client: &webhook::client::WebhookClient, announcement: &Announcement) -> Result<(), RefreshError> {
+    debug!("Sending announcement of {} to webhook {}...", announcement.match_id, &webhook.name);
+    client.send_message(&announcement.message).await.map_err(|e| RefreshError::Discord {
+        match_id: announcement.match_id,
+        webhook: webhook.name.clone(),
+        message: e.to_string(),
+    })?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! This module is about detecting the rank changes of guild members, and building the Discord messages announcing them.
+
+use std::cmp::Ordering;
+use serde::Serialize;
+use crate::announce::{GuildInfo, MedalView};
+use crate::emoji::Emojis;
+use crate::state::MemberRank;
+use crate::stratz;
+use crate::template::{Renderer, TemplateError};
+
+/// How the rank of a guild member changed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RankChange {
+    Promotion,
+    Demotion,
+}
+
+impl RankChange {
+    /// Compare the `old` and `new` ranks of a member, returning how it changed, if it did in a way worth announcing.
+    ///
+    /// Moving up and down the leaderboard happens all the time, so only entering and leaving it is announced.
+    pub fn between(old: &MemberRank, new: &MemberRank) -> Option<Self> {
+        let (Some(old_rank), Some(new_rank)) = (old.season_rank, new.season_rank) else {
+            return None
+        };
+        match (new_rank.cmp(&old_rank), old.leaderboard_rank, new.leaderboard_rank) {
+            (Ordering::Greater, _, _) => Some(Self::Promotion),
+            (Ordering::Less, _, _) => Some(Self::Demotion),
+            (Ordering::Equal, None, Some(_)) => Some(Self::Promotion),
+            (Ordering::Equal, Some(_), None) => Some(Self::Demotion),
+            (Ordering::Equal, _, _) => None,
+        }
+    }
+}
+
+/// The data of a rank change announcement, passed to the [Renderer] templates.
+#[derive(Clone, Debug, Serialize)]
+pub struct RankChangeView {
+    pub guild: GuildInfo,
+    pub steam_account_id: i64,
+    /// The Steam name of the member, or the translation of `label.anonymous`.
+    pub name: String,
+    pub change: RankChange,
+    /// The medal the member had at the previous scan.
+    pub old: MedalView,
+    /// The medal the member has now.
+    pub new: MedalView,
+    /// The leaderboard position the member had at the previous scan.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_leaderboard_rank: Option<i16>,
+    /// The leaderboard position the member has now.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_leaderboard_rank: Option<i16>,
+}
+
+/// Get the current rank of a player, as returned by Stratz.
+pub fn current_rank(player: &stratz::RankedPlayer) -> MemberRank {
+    MemberRank {
+        season_rank: player.steam_account.as_ref().and_then(|steam| steam.season_rank),
+        leaderboard_rank: player.steam_account.as_ref().and_then(|steam| steam.season_leaderboard_rank),
+    }
+}
+
+/// Get the rank of a member to compare with and store in place of the `known` one, given the `fetched` one.
+///
+/// Stratz sometimes does not return the season rank of a player, in which case the known rank is kept rather than forgotten,
+/// so that the next change is still detected and leaving the leaderboard is not announced by mistake.
+pub fn merge_rank(known: &MemberRank, fetched: MemberRank) -> MemberRank {
+    match fetched.season_rank {
+        Some(_) => fetched,
+        None => *known,
+    }
+}
+
+/// Build the message announcing the change of a guild member from the `old` to the `new` rank, or return [None] if it should not be announced.
+pub fn build(emojis: &Emojis, renderer: &Renderer, guild: &GuildInfo, player: &stratz::RankedPlayer, old: &MemberRank, new: &MemberRank) -> Result<Option<webhook::models::Message>, TemplateError> {
+    let (Some(old_rank), Some(new_rank), Some(change)) = (old.season_rank, new.season_rank, RankChange::between(old, new)) else {
+        return Ok(None)
+    };
+
+    trace!("Creating rank change view...");
+    let view = RankChangeView {
+        guild: guild.clone(),
+        steam_account_id: player.steam_account_id.unwrap_or_default(),
+        name: player.steam_account.as_ref().and_then(|steam| steam.name.clone()).unwrap_or_else(|| renderer.catalog().get("label.anonymous")),
+        change,
+        old: MedalView::new(emojis, renderer.catalog(), old_rank.into()),
+        new: MedalView::new(emojis, renderer.catalog(), new_rank.into()),
+        old_leaderboard_rank: old.leaderboard_rank,
+        new_leaderboard_rank: new.leaderboard_rank,
+    };
+
+    trace!("Rendering rank change announcement...");
+    Ok(Some(renderer.render_rank_change(&view)?))
+}
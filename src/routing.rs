@@ -0,0 +1,75 @@
+//! This module is about routing match announcements to the Discord webhooks that should receive them.
+
+use serde::Deserialize;
+use crate::stratz;
+use crate::announce::MatchResult;
+
+/// A Discord webhook announcements may be sent to.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    /// The name of the webhook, used in logs.
+    pub name: String,
+    /// The URL of the webhook.
+    pub url: String,
+    /// The filter deciding which matches should be announced on this webhook.
+    #[serde(default)]
+    pub filter: Filter,
+    /// Whether the rank changes of guild members should be announced on this webhook, if [crate::config::AnnounceConfig::ranks] is enabled.
+    #[serde(default = "default_webhook_rank_changes")]
+    pub rank_changes: bool,
+}
+
+fn default_webhook_rank_changes() -> bool {
+    true
+}
+
+/// A set of conditions a match must satisfy to be announced on a [Webhook].
+///
+/// Conditions set to [None] always pass.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filter {
+    /// The lobby types the match must have been played in.
+    pub lobby_types: Option<Vec<stratz::LobbyType>>,
+    /// The game modes the match must have been played in.
+    pub game_modes: Option<Vec<stratz::GameMode>>,
+    /// The minimum number of guild players that must be in the match.
+    pub min_players: Option<usize>,
+    /// The results the match must have ended with.
+    pub results: Option<Vec<MatchResult>>,
+    /// Whether guild players must have queued in a party, instead of alone.
+    ///
+    /// Sizing parties needs the full roster of matches, which is then fetched even if not displayed.
+    pub party_only: bool,
+}
+
+impl Filter {
+    /// Check whether a match with the given properties satisfies all conditions of the filter.
+    pub fn accepts(&self, lobby_type: &stratz::LobbyType, game_mode: &stratz::GameMode, players: usize, result: &MatchResult, stack: usize) -> bool {
+        if let Some(lobby_types) = &self.lobby_types {
+            if !lobby_types.contains(lobby_type) {
+                return false
+            }
+        }
+        if let Some(game_modes) = &self.game_modes {
+            if !game_modes.contains(game_mode) {
+                return false
+            }
+        }
+        if let Some(min_players) = self.min_players {
+            if players < min_players {
+                return false
+            }
+        }
+        if let Some(results) = &self.results {
+            if !results.contains(result) {
+                return false
+            }
+        }
+        if self.party_only && stack < 2 {
+            return false
+        }
+        true
+    }
+}
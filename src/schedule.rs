@@ -0,0 +1,189 @@
+//! This module is about deciding how long to wait between two match scans, depending on the time of day.
+
+use chrono::{DateTime, Datelike, Duration, DurationRound, TimeZone, Timelike};
+use serde::Deserialize;
+
+/// The longest period allowed between two scans, one week, well below the largest [Duration] that can be built.
+pub const MAX_PERIOD: u64 = 60 * 60 * 24 * 7;
+
+/// A rule overriding the scan period during some hours of some days of the week.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleRule {
+    /// The hours of the day the rule applies to, as a cron hour field (`0-23`).
+    pub hours: CronField,
+    /// The days of the week the rule applies to, as a cron day-of-week field (`0-7`, where both `0` and `7` are Sunday).
+    #[serde(default)]
+    pub weekdays: CronField,
+    /// The number of seconds elapsed between two match scans while the rule applies.
+    pub period: u64,
+}
+
+/// A field of a cron expression, such as `*`, `18-23`, `*/2` or `1,3,5`, matching a set of values between `0` and `63`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct CronField {
+    /// Bitset of the values matched by the field.
+    bits: u64,
+    /// The largest value written explicitly in the field, as wildcards match whatever range the field has.
+    highest: u32,
+}
+
+impl CronField {
+    /// Check whether the field matches the given `value`.
+    pub fn matches(&self, value: u32) -> bool {
+        value < 64 && self.bits & (1 << value) != 0
+    }
+
+    /// Check whether all the values written explicitly in the field are at most `max`.
+    pub fn fits(&self, max: u32) -> bool {
+        self.highest <= max
+    }
+}
+
+impl Default for CronField {
+    fn default() -> Self {
+        Self { bits: u64::MAX, highest: 0 }
+    }
+}
+
+impl TryFrom<String> for CronField {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid cron field {value:?}");
+        let mut bits: u64 = 0;
+        let mut highest: u32 = 0;
+        for part in value.split(',') {
+            let (range, step) = match part.trim().split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+                None => (part.trim(), 1),
+            };
+            let (start, end) = match range {
+                "*" => (0, 63),
+                _ => match range.split_once('-') {
+                    Some((start, end)) => (start.parse::<u32>().map_err(|_| invalid())?, end.parse::<u32>().map_err(|_| invalid())?),
+                    None => {
+                        let single = range.parse::<u32>().map_err(|_| invalid())?;
+                        (single, single)
+                    }
+                },
+            };
+            if step == 0 || start > end || end > 63 {
+                return Err(invalid());
+            }
+            if range != "*" {
+                highest = highest.max(end);
+            }
+            for v in (start..=end).step_by(step as usize) {
+                bits |= 1 << v;
+            }
+        }
+        Ok(Self { bits, highest })
+    }
+}
+
+impl ScheduleRule {
+    /// Check whether the rule applies at the given instant.
+    pub fn applies<Tz: TimeZone>(&self, instant: &DateTime<Tz>) -> bool {
+        let weekday = instant.weekday().num_days_from_sunday();
+        // Cron allows both 0 and 7 to refer to Sunday.
+        let weekday_matches = self.weekdays.matches(weekday) || (weekday == 0 && self.weekdays.matches(7));
+        weekday_matches && self.hours.matches(instant.hour())
+    }
+}
+
+/// Get the number of seconds between two scans at the given instant, using the first of the `rules` that applies, or the `default` period if none does.
+pub fn period_at<Tz: TimeZone>(rules: &[ScheduleRule], default: u64, instant: &DateTime<Tz>) -> u64 {
+    rules.iter()
+        .find(|rule| rule.applies(instant))
+        .map(|rule| rule.period)
+        .unwrap_or(default)
+}
+
+/// Get how long to wait before the next scan, starting from the given instant.
+///
+/// Since rules apply on whole hours, the wait is cut short at the first hour boundary where the period changes, so that a long period cannot delay the start of a shorter one.
+pub fn next_delay<Tz: TimeZone>(rules: &[ScheduleRule], default: u64, now: &DateTime<Tz>) -> std::time::Duration {
+    let period = period_at(rules, default, now);
+    let deadline = now.clone() + Duration::seconds(period as i64);
+    let mut boundary = now.clone().duration_trunc(Duration::hours(1)).expect("hour truncation to be valid") + Duration::hours(1);
+    while boundary < deadline {
+        if period_at(rules, default, &boundary) != period {
+            trace!("Scan period changes at {boundary:?}, waking up then.");
+            return (boundary - now.clone()).to_std().expect("boundary to be in the future");
+        }
+        boundary += Duration::hours(1);
+    }
+    std::time::Duration::from_secs(period)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use super::*;
+
+    fn field(value: &str) -> CronField {
+        CronField::try_from(String::from(value)).expect("cron field to be valid")
+    }
+
+    fn rule(hours: &str, weekdays: &str, period: u64) -> ScheduleRule {
+        ScheduleRule { hours: field(hours), weekdays: field(weekdays), period }
+    }
+
+    #[test]
+    fn cron_field_steps() {
+        let field = field("*/2");
+        assert!(field.matches(0));
+        assert!(!field.matches(1));
+        assert!(field.matches(22));
+        assert!(!field.matches(23));
+        assert!(field.fits(0));
+    }
+
+    #[test]
+    fn cron_field_lists_and_ranges() {
+        let field = field("1,3,10-12");
+        assert!(field.matches(1));
+        assert!(!field.matches(2));
+        assert!(field.matches(3));
+        assert!(!field.matches(9));
+        assert!(field.matches(10));
+        assert!(field.matches(12));
+        assert!(!field.matches(13));
+        assert!(field.fits(12));
+        assert!(!field.fits(11));
+    }
+
+    #[test]
+    fn cron_field_rejects_invalid() {
+        for value in ["", "a", "5-3", "*/0", "64", "1-64"] {
+            assert!(CronField::try_from(String::from(value)).is_err(), "{value:?} should be invalid");
+        }
+    }
+
+    #[test]
+    fn weekday_seven_is_sunday() {
+        let rule = rule("*", "7", 60);
+        let sunday = Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap();
+        let monday = Utc.with_ymd_and_hms(2023, 1, 2, 12, 0, 0).unwrap();
+        assert!(rule.applies(&sunday));
+        assert!(!rule.applies(&monday));
+    }
+
+    #[test]
+    fn next_delay_uses_period_of_matching_rule() {
+        let rules = [rule("18-23", "*", 300)];
+        let evening = Utc.with_ymd_and_hms(2023, 1, 1, 20, 0, 0).unwrap();
+        let morning = Utc.with_ymd_and_hms(2023, 1, 1, 10, 0, 0).unwrap();
+        assert_eq!(next_delay(&rules, 1800, &evening), std::time::Duration::from_secs(300));
+        assert_eq!(next_delay(&rules, 1800, &morning), std::time::Duration::from_secs(1800));
+    }
+
+    #[test]
+    fn next_delay_wakes_up_at_hour_boundary() {
+        let rules = [rule("18-23", "*", 300)];
+        let now = Utc.with_ymd_and_hms(2023, 1, 1, 17, 50, 0).unwrap();
+        assert_eq!(next_delay(&rules, 1800, &now), std::time::Duration::from_secs(600));
+    }
+}
@@ -0,0 +1,240 @@
+//! This module is about persisting the state of the bot across restarts.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::config::ParseMode;
+use crate::stratz;
+
+/// The state of the bot that must survive restarts.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    /// The ID of the last match announced for each followed guild, keyed by guild ID.
+    #[serde(default)]
+    pub last_match_ids: HashMap<i64, i64>,
+    /// The announcements that could not be sent, and should be retried on later scans.
+    #[serde(default)]
+    pub pending: Vec<PendingAnnouncement>,
+    /// The announcements of matches that were not parsed yet when they were sent, and should be edited once they are.
+    #[serde(default)]
+    pub awaiting_parse: Vec<AwaitingParse>,
+    /// The last known ranks of the members of each followed guild, keyed by guild ID and then by Steam account ID.
+    #[serde(default)]
+    pub ranks: HashMap<i64, HashMap<i64, MemberRank>>,
+}
+
+/// An announcement that could not be sent to a webhook.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingAnnouncement {
+    /// The ID of the guild the match was played by.
+    pub guild_id: i64,
+    /// The name of the webhook the announcement should be sent to.
+    pub webhook: String,
+    /// The number of times sending the announcement was attempted.
+    pub attempts: u32,
+    /// The match to announce, as returned by Stratz.
+    #[serde(rename = "match")]
+    pub match_: stratz::Match,
+    /// The details of the match fetched in addition to the guild matches, if any.
+    #[serde(default)]
+    pub details: stratz::MatchDetails,
+    /// How the match was to be announced if not parsed yet, so that retries of [ParseMode::Edit] announcements are edited once parsed too.
+    #[serde(default)]
+    pub parse: ParseMode,
+}
+
+/// An announcement sent before Stratz parsed its match.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AwaitingParse {
+    /// The ID of the guild the match was played by.
+    pub guild_id: i64,
+    /// The name of the webhook the announcement was sent to.
+    pub webhook: String,
+    /// The ID of the Discord message of the announcement.
+    pub message_id: String,
+    /// The match that was announced, as returned by Stratz.
+    #[serde(rename = "match")]
+    pub match_: stratz::Match,
+}
+
+/// The rank of a guild member, as last fetched from Stratz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemberRank {
+    /// The rank of the member in the current season, with the medal as tens and the stars as units.
+    pub season_rank: Option<u8>,
+    /// The position of the member in the leaderboard of their region, if they are in it.
+    pub leaderboard_rank: Option<i16>,
+}
+
+impl PendingAnnouncement {
+    /// Create the announcement of `match_` to be retried on the webhook named `webhook`, after it failed to be sent the first time.
+    pub fn new(guild_id: i64, webhook: String, match_: stratz::Match, details: stratz::MatchDetails, parse: ParseMode) -> Self {
+        Self { guild_id, webhook, attempts: 1, match_, details, parse }
+    }
+
+    /// Record another failed attempt at sending the announcement, returning it if it should be retried again, or [None] once it failed `max_attempts` times.
+    pub fn failed(mut self, max_attempts: u32) -> Option<Self> {
+        self.attempts += 1;
+        match self.attempts >= max_attempts {
+            true => None,
+            false => Some(self),
+        }
+    }
+}
+
+impl State {
+    /// Get the ID of the last match announced for the guild having the specified `guild_id`, or `-1` if none was ever announced.
+    pub fn last_match_id(&self, guild_id: i64) -> i64 {
+        self.last_match_ids.get(&guild_id).copied().unwrap_or(-1)
+    }
+
+    /// Set the ID of the last match announced for the guild having the specified `guild_id`.
+    pub fn set_last_match_id(&mut self, guild_id: i64, match_id: i64) {
+        self.last_match_ids.insert(guild_id, match_id);
+    }
+
+    /// Mark the match having the specified `match_id` as announced for the guild having the specified `guild_id`.
+    ///
+    /// Must be called only once the announcement was either sent or queued for retrying on every webhook, with the `failed` ones queued here.
+    pub fn mark_announced(&mut self, guild_id: i64, match_id: i64, failed: Vec<PendingAnnouncement>, awaiting: Vec<AwaitingParse>) {
+        self.pending.extend(failed);
+        self.awaiting_parse.extend(awaiting);
+        self.set_last_match_id(guild_id, match_id);
+    }
+
+    /// Get the pending announcements of the guild having the specified `guild_id`, dropping the ones for webhooks that are not among the configured `webhooks` anymore.
+    pub fn retain_pending(&mut self, guild_id: i64, webhooks: &[&str]) -> Vec<PendingAnnouncement> {
+        self.pending.retain(|pending| {
+            let configured = pending.guild_id != guild_id || webhooks.contains(&pending.webhook.as_str());
+            if !configured {
+                warn!("Dropping pending announcement for webhook {}, as it is not configured anymore.", &pending.webhook);
+            }
+            configured
+        });
+        self.pending.iter().filter(|pending| pending.guild_id == guild_id).cloned().collect()
+    }
+
+    /// Replace the pending announcements of the guild having the specified `guild_id` with the ones `still_pending` after retrying them.
+    pub fn replace_pending(&mut self, guild_id: i64, still_pending: Vec<PendingAnnouncement>) {
+        self.pending.retain(|pending| pending.guild_id != guild_id);
+        self.pending.extend(still_pending);
+    }
+}
+
+/// Error enumeration for possible state store errors.
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    /// The state could not be read from or written to the store.
+    #[error("could not access {path:?}: {source}")]
+    Io {
+        /// The path of the file being accessed.
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The stored state could not be (de)serialized.
+    #[error("could not (de)serialize state: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// Something capable of loading and saving a [State].
+pub trait StateStore {
+    /// Load the [State] from the store, returning the default state if nothing was stored yet.
+    fn load(&self) -> Result<State, StateError>;
+    /// Save the [State] to the store, replacing the previous one.
+    fn save(&self, state: &State) -> Result<(), StateError>;
+}
+
+/// A [StateStore] keeping the [State] in a local JSON file.
+#[derive(Clone, Debug)]
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+impl JsonFileStore {
+    /// Create a new store backed by the JSON file at the specified `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl StateStore for JsonFileStore {
+    fn load(&self) -> Result<State, StateError> {
+        trace!("Reading state from {:?}...", &self.path);
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!("State file {:?} does not exist, starting from a blank state.", &self.path);
+                return Ok(State::default());
+            }
+            Err(source) => return Err(StateError::Io { path: self.path.clone(), source }),
+        };
+        trace!("Parsing state...");
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    fn save(&self, state: &State) -> Result<(), StateError> {
+        trace!("Serializing state...");
+        let contents = serde_json::to_string_pretty(state)?;
+        trace!("Writing state to {:?}...", &self.path);
+        // Write to a temporary file first, so that a crash mid-write cannot corrupt the existing state.
+        let temp = self.path.with_extension("tmp");
+        std::fs::write(&temp, contents).and_then(|_| std::fs::rename(&temp, &self.path))
+            .map_err(|source| StateError::Io { path: self.path.clone(), source })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create the announcement of the match having the specified `match_id` pending on the webhook named `webhook`.
+    fn pending(guild_id: i64, webhook: &str, match_id: i64) -> PendingAnnouncement {
+        let match_ = serde_json::from_value(serde_json::json!({"id": match_id})).expect("match to be valid");
+        PendingAnnouncement::new(guild_id, String::from(webhook), match_, stratz::MatchDetails::default(), ParseMode::Ignore)
+    }
+
+    #[test]
+    fn marks_matches_announced_along_with_their_failed_sends() {
+        let mut state = State::default();
+        assert_eq!(state.last_match_id(1), -1);
+
+        state.mark_announced(1, 10, vec![pending(1, "main", 10)], Vec::new());
+
+        assert_eq!(state.last_match_id(1), 10);
+        assert_eq!(state.pending.len(), 1);
+        assert_eq!(state.pending[0].attempts, 1);
+        assert_eq!(state.last_match_id(2), -1);
+    }
+
+    #[test]
+    fn counts_failed_attempts_until_max_attempts() {
+        let retried = pending(1, "main", 10).failed(3).expect("announcement to be retried again");
+        assert_eq!(retried.attempts, 2);
+        assert!(retried.failed(3).is_none());
+        assert!(pending(1, "main", 10).failed(2).is_none());
+    }
+
+    #[test]
+    fn drops_pending_announcements_of_removed_webhooks() {
+        let mut state = State { pending: vec![pending(1, "main", 10), pending(1, "removed", 10), pending(2, "removed", 20)], ..State::default() };
+
+        let retried = state.retain_pending(1, &["main"]);
+
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].webhook, "main");
+        let remaining: Vec<(i64, &str)> = state.pending.iter().map(|pending| (pending.guild_id, pending.webhook.as_str())).collect();
+        assert_eq!(remaining, vec![(1, "main"), (2, "removed")]);
+    }
+
+    #[test]
+    fn replaces_pending_announcements_of_a_single_guild() {
+        let mut state = State { pending: vec![pending(1, "main", 10), pending(1, "main", 11), pending(2, "main", 20)], ..State::default() };
+
+        let still_pending = pending(1, "main", 11).failed(5).into_iter().collect();
+        state.replace_pending(1, still_pending);
+
+        let remaining: Vec<(i64, u32)> = state.pending.iter().map(|pending| (pending.guild_id, pending.attempts)).collect();
+        assert_eq!(remaining, vec![(2, 1), (1, 2)]);
+    }
+}
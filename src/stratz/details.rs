@@ -0,0 +1,107 @@
+//! This module is about fetching many matches at once by ID, including the data the guild matches query does not provide.
+
+use std::collections::HashMap;
+use graphql_client::GraphQLQuery;
+use serde::{Deserialize, Serialize};
+use super::{Byte, Client, Long, Match, Player, Short, StratzError};
+
+/// Query to fetch the matches with the specified IDs, with all their players.
+#[derive(GraphQLQuery)]
+#[graphql(schema_path="src/stratz_schema.gql", query_path="src/match_details.gql", response_derives="Clone,Debug,Serialize")]
+struct MatchDetailsQuery;
+
+/// The details of a match fetched in addition to the ones of the guild matches query.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchDetails {
+    /// All the players of the match, including the ones not in the guild.
+    #[serde(default)]
+    pub roster: Option<Vec<Player>>,
+}
+
+impl MatchDetails {
+    /// Extract the details of a match fetched with [Client::fetch_full_matches].
+    pub fn from_full_match(full: &Match) -> Self {
+        Self {
+            roster: full.players.as_ref().map(|players| players.iter().flatten().cloned().collect()),
+        }
+    }
+}
+
+impl Client {
+    /// Fetch all the matches having the specified `match_ids` in a single request, keyed by match ID.
+    ///
+    /// Unlike the ones of the guild matches query, the returned matches include all their players, and not only the guild members.
+    /// Matches Stratz does not know are missing from the result.
+    pub async fn fetch_full_matches(&self, match_ids: &[i64]) -> Result<HashMap<i64, Match>, StratzError> {
+        debug!("Fetching {} matches by ID", match_ids.len());
+        let vars = match_details_query::Variables { match_ids: match_ids.iter().copied().map(Some).collect() };
+        let response = self.query::<MatchDetailsQuery>(vars).await?;
+        let matches = response.data.and_then(|data| data.matches).unwrap_or_default();
+
+        trace!("Converting matches...");
+        let mut full = HashMap::new();
+        for match_ in matches.into_iter().flatten() {
+            let Some(id) = match_.id else {
+                warn!("Ignoring match without an ID.");
+                continue
+            };
+            // Both queries select the same fields, so their matches have the same serialized representation.
+            let match_: Match = serde_json::to_value(match_).and_then(serde_json::from_value)?;
+            full.insert(id, match_);
+        }
+        Ok(full)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graphql_parser::query::{Definition, OperationDefinition, Selection, SelectionSet};
+
+    /// Parse the query in `source`, returning its top level selection set.
+    fn parse(source: &str) -> SelectionSet {
+        let document = graphql_parser::parse_query(source).expect("query to be valid");
+        match document.definitions.into_iter().next() {
+            Some(Definition::Operation(OperationDefinition::Query(query))) => query.selection_set,
+            _ => panic!("expected a named query"),
+        }
+    }
+
+    /// Follow the fields of `path` down from `set`.
+    fn descend<'a>(set: &'a SelectionSet, path: &[&str]) -> &'a SelectionSet {
+        path.iter().fold(set, |set, name| {
+            set.items.iter()
+                .find_map(|item| match item {
+                    Selection::Field(field) if &field.name == name => Some(&field.selection_set),
+                    _ => None,
+                })
+                .unwrap_or_else(|| panic!("expected a {name} field"))
+        })
+    }
+
+    /// Render the fields selected by `set`, ignoring arguments and whitespace.
+    fn render(set: &SelectionSet) -> String {
+        set.items.iter()
+            .map(|item| match item {
+                Selection::Field(field) => {
+                    let name = match &field.alias {
+                        Some(alias) => format!("{alias}: {}", &field.name),
+                        None => field.name.clone(),
+                    };
+                    match field.selection_set.items.is_empty() {
+                        true => name,
+                        false => format!("{name} {{ {} }}", render(&field.selection_set)),
+                    }
+                }
+                _ => panic!("fragments are not supported"),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn match_selections_are_identical() {
+        let guild = parse(include_str!("../latest_guild_matches.gql"));
+        let details = parse(include_str!("../match_details.gql"));
+        assert_eq!(render(descend(&guild, &["guild", "matches"])), render(descend(&details, &["matches"])));
+    }
+}
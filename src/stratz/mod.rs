@@ -0,0 +1,429 @@
+//! This module is about performing GraphQL requests to [STRATZ](https://stratz.com/api) to fetch data.
+
+use std::sync::Mutex;
+use std::time::Duration;
+use graphql_client::GraphQLQuery;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+mod details;
+pub use details::MatchDetails;
+mod ranks;
+pub use ranks::RankedPlayer;
+
+/// Binding for the `Long` type of the GraphQL schema to [i64].
+type Short = i16;
+type Long = i64;
+type Byte = u8;
+
+/// Query to fetch a page of the latest matches of a specific guild.
+#[derive(GraphQLQuery)]
+#[graphql(schema_path="src/stratz_schema.gql", query_path="src/latest_guild_matches.gql", response_derives="Clone,Debug,PartialEq,Serialize")]
+struct MatchesQuery;
+pub type Response = graphql_client::Response<matches_query::ResponseData>;
+pub use matches_query::ResponseData;
+pub use matches_query::LobbyTypeEnum as LobbyType;
+pub use matches_query::GameModeEnumType as GameMode;
+pub use matches_query::MatchesQueryGuild as Guild;
+pub use matches_query::MatchesQueryGuildMatches as Match;
+pub use matches_query::MatchesQueryGuildMatchesPlayers as Player;
+pub use matches_query::MatchesQueryGuildMatchesPlayersHero as Hero;
+pub use matches_query::MatchesQueryGuildMatchesPlayersSteamAccount as Steam;
+pub use matches_query::MatchPlayerAward as Award;
+pub use matches_query::LeaverStatusEnum as LeaverStatus;
+pub use matches_query::MatchLaneType as Lane;
+pub use matches_query::MatchPlayerRoleType as Role;
+
+/// Query to fetch the constants of all heroes.
+#[derive(GraphQLQuery)]
+#[graphql(schema_path="src/stratz_schema.gql", query_path="src/hero_constants.gql", response_derives="Clone,Debug")]
+struct HeroConstantsQuery;
+pub use hero_constants_query::HeroConstantsQueryConstantsHeroes as HeroConstant;
+
+/// Query to fetch the constants of all items.
+#[derive(GraphQLQuery)]
+#[graphql(schema_path="src/stratz_schema.gql", query_path="src/item_constants.gql", response_derives="Clone,Debug")]
+struct ItemConstantsQuery;
+pub use item_constants_query::ItemConstantsQueryConstantsItems as ItemConstant;
+
+/// Error enumeration for possible Stratz errors.
+#[derive(Debug, thiserror::Error)]
+pub enum StratzError {
+    /// An error occurred while performing a request to Stratz.
+    #[error("request failed: {0}")]
+    Request(#[source] reqwest::Error),
+    /// Stratz kept answering with an error status until no retries were left.
+    #[error("Stratz answered with HTTP status {status} after {attempts} attempts")]
+    Status {
+        /// The last status Stratz answered with.
+        status: StatusCode,
+        /// The number of requests performed.
+        attempts: u32,
+    },
+    /// The response of a request could not be deserialized.
+    #[error("could not parse response with HTTP status {status}: {source}")]
+    Parse {
+        /// The status Stratz answered with.
+        status: StatusCode,
+        source: reqwest::Error,
+    },
+    /// The response contained GraphQL errors.
+    #[error("Stratz returned GraphQL errors: {}", .messages.join("; "))]
+    GraphQL {
+        /// The messages of the returned errors.
+        messages: Vec<String>,
+    },
+    /// The response could not be converted to the types of another query.
+    #[error("could not convert response: {0}")]
+    Convert(#[from] serde_json::Error),
+}
+
+/// The default URL of the Stratz GraphQL API.
+///
+/// The JWT is sent in the `Authorization` header rather than in the URL, so that it does not end up in logged request errors.
+pub const DEFAULT_API_URL: &str = "https://api.stratz.com/graphql";
+
+/// Configuration of how failed requests to Stratz are retried.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// The maximum number of times a failed request is retried.
+    pub max_retries: u32,
+    /// The number of milliseconds to wait at most before the first retry; doubled on every further retry.
+    pub base_delay_ms: u64,
+    /// The number of milliseconds to wait at most before any retry.
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            base_delay_ms: 1000,
+            max_delay_ms: 60_000,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Get how long to wait before the retry number `attempt`, counting from zero, using exponential backoff with full jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let cap = self.base_delay_ms.saturating_mul(1 << attempt.min(32)).min(self.max_delay_ms);
+        Duration::from_millis(fastrand::u64(0..=cap))
+    }
+}
+
+/// A rate limit window of the Stratz API.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitWindow {
+    /// The number of requests allowed in the window.
+    pub limit: u64,
+    /// The number of requests still available in the window.
+    pub remaining: u64,
+}
+
+/// The rate limits of the Stratz API, as returned in the `X-RateLimit-*` headers of the last response.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RateLimit {
+    /// The limit over one second.
+    pub second: Option<RateLimitWindow>,
+    /// The limit over one minute.
+    pub minute: Option<RateLimitWindow>,
+    /// The limit over one hour.
+    pub hour: Option<RateLimitWindow>,
+    /// The limit over one day.
+    pub day: Option<RateLimitWindow>,
+}
+
+impl RateLimit {
+    /// Parse the rate limits from the headers of a response, if any is present.
+    fn from_headers(headers: &reqwest::header::HeaderMap) -> Option<Self> {
+        let window = |name: &str| -> Option<RateLimitWindow> {
+            let parse = |key: String| headers.get(key)?.to_str().ok()?.parse::<u64>().ok();
+            Some(RateLimitWindow {
+                limit: parse(format!("x-ratelimit-limit-{name}"))?,
+                remaining: parse(format!("x-ratelimit-remaining-{name}"))?,
+            })
+        };
+        let rate_limit = Self {
+            second: window("second"),
+            minute: window("minute"),
+            hour: window("hour"),
+            day: window("day"),
+        };
+        match (rate_limit.second, rate_limit.minute, rate_limit.hour, rate_limit.day) {
+            (None, None, None, None) => None,
+            _ => Some(rate_limit),
+        }
+    }
+
+    /// Suggest a minimum delay before the next scan, so that the remaining requests of windows nearly exhausted are spread over the rest of the window.
+    ///
+    /// Windows with more than a tenth of their requests remaining are not considered.
+    pub fn suggested_delay(&self) -> Option<Duration> {
+        [(self.second, 1), (self.minute, 60), (self.hour, 60 * 60), (self.day, 60 * 60 * 24)].into_iter()
+            .filter_map(|(window, length)| window.map(|window| (window, length)))
+            .filter(|(window, _)| window.remaining * 10 < window.limit)
+            .map(|(window, length)| Duration::from_secs(length / window.remaining.max(1)))
+            .max()
+    }
+}
+
+/// A client for the Stratz API, which retries failed requests and keeps track of rate limits.
+pub struct Client {
+    http: reqwest::Client,
+    api_url: String,
+    jwt: String,
+    retry: RetryConfig,
+    rate_limit: Mutex<Option<RateLimit>>,
+}
+
+impl Client {
+    /// Create a new client for the API at `api_url`, authenticating with the specified `jwt`.
+    pub fn new(http: reqwest::Client, api_url: impl Into<String>, jwt: String, retry: RetryConfig) -> Self {
+        Self { http, api_url: api_url.into(), jwt, retry, rate_limit: Mutex::new(None) }
+    }
+
+    /// Get the rate limits returned with the last response, if any.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().expect("rate limit mutex to not be poisoned")
+    }
+
+    /// Post the given GraphQL `body`, retrying on connection errors, server errors and rate limiting.
+    async fn post<B: Serialize>(&self, body: &B) -> Result<reqwest::Response, StratzError> {
+        let mut attempt: u32 = 0;
+        loop {
+            trace!("Posting request, attempt {}...", attempt + 1);
+            let result = self.http.post(&self.api_url).bearer_auth(&self.jwt).json(body).send().await;
+
+            let (delay, failure) = match result {
+                Ok(resp) => {
+                    if let Some(rate_limit) = RateLimit::from_headers(resp.headers()) {
+                        trace!("Rate limits are: {rate_limit:?}");
+                        *self.rate_limit.lock().expect("rate limit mutex to not be poisoned") = Some(rate_limit);
+                    }
+                    let status = resp.status();
+                    if status == StatusCode::TOO_MANY_REQUESTS {
+                        let retry_after = resp.headers().get(reqwest::header::RETRY_AFTER)
+                            .and_then(|value| value.to_str().ok())
+                            .and_then(|value| value.parse::<u64>().ok())
+                            .map(Duration::from_secs)
+                            .map(|delay| delay.min(Duration::from_millis(self.retry.max_delay_ms)));
+                        warn!("Rate limited by Stratz, retry after: {retry_after:?}");
+                        (retry_after.unwrap_or_else(|| self.retry.backoff(attempt)), StratzError::Status { status, attempts: attempt + 1 })
+                    }
+                    else if status.is_server_error() {
+                        warn!("Stratz returned server error {status}");
+                        (self.retry.backoff(attempt), StratzError::Status { status, attempts: attempt + 1 })
+                    }
+                    else {
+                        return Ok(resp);
+                    }
+                }
+                Err(err) if err.is_timeout() || err.is_connect() || err.is_request() => {
+                    warn!("Transient error while performing request: {err}");
+                    (self.retry.backoff(attempt), StratzError::Request(err))
+                }
+                Err(err) => return Err(StratzError::Request(err)),
+            };
+
+            if attempt >= self.retry.max_retries {
+                return Err(failure);
+            }
+            debug!("Retrying request in {delay:?}...");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Perform the GraphQL query `Q` with the given variables, failing if the response contains any GraphQL error.
+    async fn query<Q: GraphQLQuery>(&self, vars: Q::Variables) -> Result<graphql_client::Response<Q::ResponseData>, StratzError> {
+        trace!("Building query...");
+        let body = Q::build_query(vars);
+        trace!("Posting request...");
+        let resp = self.post(&body).await?;
+        trace!("Parsing response...");
+        let status = resp.status();
+        let data = resp.json::<graphql_client::Response<Q::ResponseData>>().await.map_err(|source| StratzError::Parse { status, source })?;
+        trace!("Successfully parsed response!");
+
+        trace!("Ensuring there are no errors in the data...");
+        if let Some(errors) = &data.errors {
+            let messages = errors.iter().map(|error| error.message.clone()).collect();
+            return Err(StratzError::GraphQL { messages });
+        }
+
+        Ok(data)
+    }
+
+    /// Fetch the latest `take` matches of the guild having the specified `guild_id`, skipping the `skip` most recent ones, along with its `members` if requested.
+    pub async fn fetch_matches(&self, guild_id: i64, take: i64, skip: i64, members: bool) -> Result<Response, StratzError> {
+        debug!("Fetching {take} matches of guild {guild_id}, skipping {skip}");
+        self.query::<MatchesQuery>(matches_query::Variables { guild_id, take, skip, members }).await
+    }
+
+    /// Fetch the constants of all heroes currently in the game.
+    pub async fn fetch_heroes(&self) -> Result<Vec<HeroConstant>, StratzError> {
+        debug!("Fetching hero constants");
+        let response = self.query::<HeroConstantsQuery>(hero_constants_query::Variables).await?;
+        let heroes = response.data
+            .and_then(|data| data.constants)
+            .and_then(|constants| constants.heroes)
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .collect();
+        Ok(heroes)
+    }
+
+    /// Fetch the constants of all items currently in the game.
+    pub async fn fetch_items(&self) -> Result<Vec<ItemConstant>, StratzError> {
+        debug!("Fetching item constants");
+        let response = self.query::<ItemConstantsQuery>(item_constants_query::Variables).await?;
+        let items = response.data
+            .and_then(|data| data.constants)
+            .and_then(|constants| constants.items)
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .collect();
+        Ok(items)
+    }
+
+    /// Fetch all matches of the guild having the specified `guild_id` more recent than `after_match_id`, paging backwards `take` matches at a time.
+    ///
+    /// Paging stops at the first page containing `after_match_id` or an older match, at the first incomplete page, or after `max_pages` pages, whichever comes first.
+    /// If `after_match_id` is negative, nothing was announced yet, and only the first page is fetched.
+    ///
+    /// The pages are merged in a single [Response], whose guild has the matches of all pages, from the most recent to the oldest.
+    /// The guild `members` are requested with the first page only, if at all.
+    pub async fn fetch_new_matches(&self, guild_id: i64, take: i64, after_match_id: i64, max_pages: i64, members: bool) -> Result<Response, StratzError> {
+        let mut response = self.fetch_matches(guild_id, take, 0, members).await?;
+        if after_match_id < 0 {
+            trace!("Nothing was announced yet, not fetching more pages.");
+            return Ok(response);
+        }
+
+        let mut page: i64 = 1;
+        loop {
+            trace!("Checking if page {page} reached the last announced match...");
+            let Some(matches) = response.data.as_ref().and_then(|data| data.guild.as_ref()).and_then(|guild| guild.matches.as_ref()) else {
+                trace!("Response has no matches, not fetching more pages.");
+                return Ok(response);
+            };
+            let reached = matches.iter().flatten().any(|match_| match_.id.map(|id| id <= after_match_id).unwrap_or(false));
+            if reached || (matches.len() as i64) < take * page {
+                trace!("Fetched all new matches in {page} pages.");
+                return Ok(response);
+            }
+            if page >= max_pages {
+                warn!("Stopped fetching matches of guild {guild_id} after {page} pages, some matches may not be announced.");
+                return Ok(response);
+            }
+
+            trace!("Fetching page {}...", page + 1);
+            let next = self.fetch_matches(guild_id, take, take * page, false).await?;
+            let next_matches = next.data.and_then(|data| data.guild).and_then(|guild| guild.matches).unwrap_or_default();
+            response.data.as_mut()
+                .and_then(|data| data.guild.as_mut())
+                .and_then(|guild| guild.matches.as_mut())
+                .expect("matches to have been checked to exist")
+                .extend(next_matches);
+            page += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, Mock, ServerGuard};
+    use super::*;
+
+    /// Create a client for the API served by `server`, which does not retry failed requests.
+    fn client(server: &ServerGuard) -> Client {
+        let retry = RetryConfig { max_retries: 0, ..RetryConfig::default() };
+        Client::new(reqwest::Client::new(), format!("{}/graphql", server.url()), String::from("jwt"), retry)
+    }
+
+    /// Serve the page of guild matches skipping the `skip` most recent ones, containing the matches with the given `ids`.
+    async fn page(server: &mut ServerGuard, skip: i64, ids: &[i64]) -> Mock {
+        let matches: Vec<serde_json::Value> = ids.iter().map(|id| serde_json::json!({"id": id})).collect();
+        server.mock("POST", "/graphql")
+            .match_body(Matcher::PartialJson(serde_json::json!({"variables": {"skip": skip}})))
+            .with_header("content-type", "application/json")
+            .with_body(serde_json::json!({"data": {"guild": {"id": 1, "matches": matches}}}).to_string())
+            .create_async().await
+    }
+
+    /// Get the IDs of the matches in a `response`.
+    fn ids(response: &Response) -> Vec<i64> {
+        let matches = response.data.as_ref().unwrap().guild.as_ref().unwrap().matches.as_ref().unwrap();
+        matches.iter().flatten().filter_map(|match_| match_.id).collect()
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_last_announced_match() {
+        let mut server = mockito::Server::new_async().await;
+        let first = server.mock("POST", "/graphql")
+            .match_body(Matcher::PartialJson(serde_json::json!({"variables": {"skip": 0, "members": true}})))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"data": {"guild": {"id": 1, "members": [], "matches": [{"id": 14}, {"id": 13}]}}}"#)
+            .create_async().await;
+        let second = server.mock("POST", "/graphql")
+            .match_body(Matcher::PartialJson(serde_json::json!({"variables": {"skip": 2, "members": false}})))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"data": {"guild": {"id": 1, "matches": [{"id": 12}, {"id": 10}]}}}"#)
+            .create_async().await;
+        let third = page(&mut server, 4, &[9, 8]).await.expect(0);
+
+        let response = client(&server).fetch_new_matches(1, 2, 10, 5, true).await.unwrap();
+
+        assert_eq!(ids(&response), vec![14, 13, 12, 10]);
+        first.assert_async().await;
+        second.assert_async().await;
+        third.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn stops_at_a_short_page() {
+        let mut server = mockito::Server::new_async().await;
+        let first = page(&mut server, 0, &[14, 13]).await;
+        let second = page(&mut server, 2, &[12]).await;
+        let third = page(&mut server, 4, &[]).await.expect(0);
+
+        let response = client(&server).fetch_new_matches(1, 2, 1, 5, false).await.unwrap();
+
+        assert_eq!(ids(&response), vec![14, 13, 12]);
+        first.assert_async().await;
+        second.assert_async().await;
+        third.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn stops_after_max_pages() {
+        let mut server = mockito::Server::new_async().await;
+        let first = page(&mut server, 0, &[14, 13]).await;
+        let second = page(&mut server, 2, &[12, 11]).await;
+        let third = page(&mut server, 4, &[10, 9]).await.expect(0);
+
+        let response = client(&server).fetch_new_matches(1, 2, 1, 2, false).await.unwrap();
+
+        assert_eq!(ids(&response), vec![14, 13, 12, 11]);
+        first.assert_async().await;
+        second.assert_async().await;
+        third.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn fetches_one_page_when_nothing_was_announced() {
+        let mut server = mockito::Server::new_async().await;
+        let first = page(&mut server, 0, &[14, 13]).await;
+        let second = page(&mut server, 2, &[12, 11]).await.expect(0);
+
+        let response = client(&server).fetch_new_matches(1, 2, -1, 5, false).await.unwrap();
+
+        assert_eq!(ids(&response), vec![14, 13]);
+        first.assert_async().await;
+        second.assert_async().await;
+    }
+}
@@ -0,0 +1,22 @@
+//! This module is about fetching the current ranks of many players at once.
+
+use graphql_client::GraphQLQuery;
+use super::{Byte, Client, Long, Short, StratzError};
+
+/// Query to fetch the current ranks of the players with the specified Steam account IDs.
+#[derive(GraphQLQuery)]
+#[graphql(schema_path="src/stratz_schema.gql", query_path="src/player_ranks.gql", response_derives="Clone,Debug")]
+struct PlayerRanksQuery;
+pub use player_ranks_query::PlayerRanksQueryPlayers as RankedPlayer;
+
+impl Client {
+    /// Fetch the current ranks of all the players having the specified `steam_account_ids` in a single request.
+    ///
+    /// Players Stratz does not know are missing from the result.
+    pub async fn fetch_player_ranks(&self, steam_account_ids: &[i64]) -> Result<Vec<RankedPlayer>, StratzError> {
+        debug!("Fetching ranks of {} players", steam_account_ids.len());
+        let vars = player_ranks_query::Variables { steam_account_ids: steam_account_ids.iter().copied().map(Some).collect() };
+        let response = self.query::<PlayerRanksQuery>(vars).await?;
+        Ok(response.data.and_then(|data| data.players).unwrap_or_default().into_iter().flatten().collect())
+    }
+}
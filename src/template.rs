@@ -0,0 +1,425 @@
+//! This module is about rendering match announcements from user-editable [MiniJinja](https://docs.rs/minijinja) templates.
+//!
+//! Every part of the announcement is a separate template, rendered with the match view model built by [crate::announce] as context.
+//! The `player` template is not rendered on its own, but can be included by the others with `{% include "player" %}`.
+//! Translated strings are available through the `t` function, such as `{{ t("label.duration") }}`.
+
+use serde::{Deserialize, Serialize};
+use crate::locale::Catalog;
+
+/// The maximum number of fields Discord accepts in an embed.
+pub const MAX_FIELDS: usize = 25;
+
+/// The maximum number of characters Discord accepts in the name of an embed field.
+const MAX_FIELD_NAME: usize = 256;
+
+/// The maximum number of characters Discord accepts in the value of an embed field.
+const MAX_FIELD_VALUE: usize = 1024;
+
+/// The name of the fields continuing the value of the previous one: a zero width space, as Discord rejects empty names.
+const CONTINUATION_NAME: &str = "\u{200B}";
+
+/// Configuration of the templates of the announcements.
+///
+/// Parts rendering to an empty string are left out of the announcement.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplateConfig {
+    /// The text of the message outside of the embed.
+    pub content: String,
+    /// The name of the author of the embed.
+    pub author_name: String,
+    /// The URL the author of the embed links to.
+    pub author_url: String,
+    /// The URL of the icon of the author of the embed.
+    pub author_icon: String,
+    /// The title of the embed.
+    pub title: String,
+    /// The color of the embed, as an hexadecimal RGB value such as `#2ACB4F`.
+    pub color: String,
+    /// The fields of the embed, at most [MAX_FIELDS].
+    pub fields: Vec<FieldTemplate>,
+    /// The footer of the embed.
+    pub footer: String,
+    /// The timestamp of the embed, in RFC 3339 format.
+    pub timestamp: String,
+    /// The line describing a single player, meant to be included by other templates.
+    pub player: String,
+    /// The title of the embed announcing a rank change of a guild member.
+    pub rank_title: String,
+    /// The description of the embed announcing a rank change of a guild member.
+    pub rank_description: String,
+    /// The color of the embed announcing a rank change of a guild member, as an hexadecimal RGB value.
+    pub rank_color: String,
+}
+
+/// Configuration of the templates of an embed field.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldTemplate {
+    /// The name of the field.
+    pub name: String,
+    /// The value of the field.
+    pub value: String,
+    /// Whether the field should be displayed side by side with the other inline fields.
+    #[serde(default)]
+    pub inline: bool,
+}
+
+impl Default for TemplateConfig {
+    fn default() -> Self {
+        Self {
+            content: String::from("https://stratz.com/matches/{{ id }}"),
+            author_name: String::from("{{ guild.name }}"),
+            author_url: String::from("https://stratz.com/guilds/{{ guild.id }}"),
+            author_icon: String::from("https://steamusercontent-a.akamaihd.net/ugc/{{ guild.logo }}/"),
+            title: String::from("{% if abandoned %}{{ t(\"label.abandoned\") }}{% else %}{{ result_name }}{% endif %} · {{ lobby_type_name }} · {{ game_mode_name }}{% if stack > 1 %} · {{ t(\"label.stack\") }} {{ stack }}{% endif %}"),
+            color: String::from(r##"{% if abandoned %}#F28C28{% else %}{{ {"victory": "#2ACB4F", "defeat": "#EC041F"}[result]|default("#A1A1A1") }}{% endif %}"##),
+            fields: vec![
+                FieldTemplate {
+                    name: String::from("<:radiant:958274781919207505> {{ t(\"label.radiant\") }}"),
+                    value: String::from("{% for player in radiant %}{% include \"player\" %}\n{% endfor %}"),
+                    inline: true,
+                },
+                FieldTemplate {
+                    name: String::from("<:dire:958274694203719740> {{ t(\"label.dire\") }}"),
+                    value: String::from("{% for player in dire %}{% include \"player\" %}\n{% endfor %}"),
+                    inline: true,
+                },
+                FieldTemplate {
+                    name: String::from(":crossed_swords: {{ t(\"label.score\") }}"),
+                    value: String::from(concat!(
+                        "{% if winner is defined %}{{ t(\"label.winner\") }}: **{{ winner_name }}**\n{% endif %}",
+                        "{% if radiant_kills is defined and dire_kills is defined %}{{ t(\"label.kills\") }}: {{ radiant_kills }} - {{ dire_kills }}\n{% endif %}",
+                        "{% if radiant_destroyed is defined and dire_destroyed is defined %}",
+                        "{{ t(\"label.towers\") }}: {{ radiant_destroyed.towers }} - {{ dire_destroyed.towers }}\n",
+                        "{{ t(\"label.barracks\") }}: {{ radiant_destroyed.barracks }} - {{ dire_destroyed.barracks }}\n",
+                        "{% endif %}",
+                        "{% if first_blood_time is defined %}{{ t(\"label.first_blood\") }}: {{ first_blood_time }}{% endif %}",
+                    )),
+                    inline: false,
+                },
+                FieldTemplate {
+                    name: String::from(":medal: {{ t(\"label.average_rank\") }}"),
+                    value: String::from("{% if average_rank is defined %}{% if average_rank.emoji is defined %}{{ average_rank.emoji }} {% endif %}{{ average_rank.name }}{% endif %}"),
+                    inline: false,
+                },
+                FieldTemplate {
+                    name: String::from(":clock3: {{ t(\"label.duration\") }}"),
+                    value: String::from("{{ duration }}"),
+                    inline: false,
+                },
+            ],
+            footer: String::from("{% if awaiting_parse %}{{ t(\"label.parse_pending\") }}{% endif %}"),
+            timestamp: String::from("{{ end }}"),
+            player: String::from(
+                "{% if player.position is defined %}{{ [\":one:\", \":two:\", \":three:\", \":four:\", \":five:\"][player.position - 1] }} {% endif %}\
+                {{ player.hero }} \
+                {% if not full_roster %}{{ player.name }}{% elif player.guild_member %}**{{ player.name }}**{% else %}*{{ player.name }}*{% endif %}\
+                {% if player.medal is defined %} {{ player.medal.emoji|default(\"(\" ~ player.medal.name ~ \")\") }}{% endif %} \
+                [{{ player.kills|default(\"?\") }}/{{ player.deaths|default(\"?\") }}/{{ player.assists|default(\"?\") }}]\
+                {% if player.imp is defined %} `{{ player.imp|signed }}`{% endif %}\
+                {% if player.party is defined %} :link:{{ player.party }}{% elif player.solo and player.guild_member %} :bust_in_silhouette:{% endif %}\
+                {% if player.leaver_status is defined %} {% if player.abandoned %}:door:{% else %}:electric_plug:{% endif %} *{{ player.leaver_status_name }}*{% endif %}\
+                {% if player.intentional_feeding %} :skull:{% endif %}\
+                {% if player.guild_member %}\
+                {% for stat in player.stats %} · {{ stat.text }} {{ stat.name }}{% endfor %}\
+                {% if player.award is defined %} :medal: **{{ player.award_name }}**{% endif %}\
+                {% if player.items %}\n{{ player.items|join(\" \") }}{% endif %}\
+                {% if player.backpack %} ({{ player.backpack|join(\" \") }}){% endif %}\
+                {% if player.neutral is defined %} · {{ player.neutral }}{% endif %}\
+                {% endif %}"
+            ),
+            rank_title: String::from(r##"{% if change == "promotion" %}:tada: {{ t("label.promotion") }}{% else %}:pensive: {{ t("label.demotion") }}{% endif %}"##),
+            rank_description: String::from(concat!(
+                "**{{ name }}**: ",
+                "{% if old.emoji is defined %}{{ old.emoji }} {% endif %}{{ old.name }}{% if old_leaderboard_rank is defined %} #{{ old_leaderboard_rank }}{% endif %}",
+                " → ",
+                "{% if new.emoji is defined %}{{ new.emoji }} {% endif %}{{ new.name }}{% if new_leaderboard_rank is defined %} #{{ new_leaderboard_rank }}{% endif %}",
+            )),
+            rank_color: String::from(r##"{{ {"promotion": "#2ACB4F", "demotion": "#EC041F"}[change]|default("#A1A1A1") }}"##),
+        }
+    }
+}
+
+/// Error enumeration for possible template errors.
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    /// A template could not be parsed or rendered.
+    #[error("error in template {name}: {source}")]
+    Template {
+        /// The name of the template, such as `title` or `fields[0].value`.
+        name: String,
+        source: minijinja::Error,
+    },
+    /// The `color` or `rank_color` template did not render to an hexadecimal RGB value.
+    #[error("color template rendered to {0:?}, which is not an hexadecimal RGB value")]
+    Color(String),
+}
+
+/// The announcement templates, compiled and ready to be rendered.
+#[derive(Debug)]
+pub struct Renderer {
+    env: minijinja::Environment<'static>,
+    catalog: Catalog,
+    /// Whether each field of the embed is inline.
+    inline_fields: Vec<bool>,
+}
+
+impl Renderer {
+    /// Compile all the templates of the given configuration, translating strings with the given `catalog`, failing on the first syntax error.
+    pub fn new(config: &TemplateConfig, catalog: Catalog) -> Result<Self, TemplateError> {
+        let mut env = minijinja::Environment::new();
+        env.add_filter("signed", |value: i64| format!("{value:+}"));
+        let translations = catalog.clone();
+        env.add_function("t", move |id: &str| translations.get(id));
+
+        let mut templates: Vec<(String, String)> = vec![
+            (String::from("content"), config.content.clone()),
+            (String::from("author_name"), config.author_name.clone()),
+            (String::from("author_url"), config.author_url.clone()),
+            (String::from("author_icon"), config.author_icon.clone()),
+            (String::from("title"), config.title.clone()),
+            (String::from("color"), config.color.clone()),
+            (String::from("footer"), config.footer.clone()),
+            (String::from("timestamp"), config.timestamp.clone()),
+            (String::from("player"), config.player.clone()),
+            (String::from("rank_title"), config.rank_title.clone()),
+            (String::from("rank_description"), config.rank_description.clone()),
+            (String::from("rank_color"), config.rank_color.clone()),
+        ];
+        for (index, field) in config.fields.iter().enumerate() {
+            templates.push((format!("fields[{index}].name"), field.name.clone()));
+            templates.push((format!("fields[{index}].value"), field.value.clone()));
+        }
+        for (name, source) in templates {
+            trace!("Compiling template {name}...");
+            env.add_template_owned(name.clone(), source).map_err(|source| TemplateError::Template { name, source })?;
+        }
+
+        Ok(Self {
+            env,
+            catalog,
+            inline_fields: config.fields.iter().map(|field| field.inline).collect(),
+        })
+    }
+
+    /// Get the catalog strings are translated with.
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    /// Render the template called `name` with the given context, trimming whitespace around the result.
+    fn render_one<S: Serialize>(&self, name: &str, ctx: &S) -> Result<String, TemplateError> {
+        trace!("Rendering template {name}...");
+        let template = |source| TemplateError::Template { name: name.to_string(), source };
+        let rendered = self.env.get_template(name).map_err(template)?.render(ctx).map_err(template)?;
+        Ok(rendered.trim().to_string())
+    }
+
+    /// Render the whole announcement message with the given context.
+    pub fn render<S: Serialize>(&self, ctx: &S) -> Result<webhook::models::Message, TemplateError> {
+        let content = self.render_one("content", ctx)?;
+        let author_name = self.render_one("author_name", ctx)?;
+        let author_url = self.render_one("author_url", ctx)?;
+        let author_icon = self.render_one("author_icon", ctx)?;
+        let title = self.render_one("title", ctx)?;
+        let color = self.render_one("color", ctx)?;
+        let footer = self.render_one("footer", ctx)?;
+        let timestamp = self.render_one("timestamp", ctx)?;
+        let mut fields: Vec<(String, String, bool)> = Vec::new();
+        for (index, inline) in self.inline_fields.iter().enumerate() {
+            let name = self.render_one(&format!("fields[{index}].name"), ctx)?;
+            let value = self.render_one(&format!("fields[{index}].value"), ctx)?;
+            // Discord rejects fields with an empty name or value.
+            if name.is_empty() || value.is_empty() {
+                trace!("Leaving out fields[{index}], as it rendered empty.");
+                continue
+            }
+            for (part, chunk) in split_value(&value).into_iter().enumerate() {
+                let name = match part {
+                    0 => truncate(&name, MAX_FIELD_NAME),
+                    _ => String::from(CONTINUATION_NAME),
+                };
+                fields.push((name, chunk, *inline));
+            }
+        }
+        if fields.len() > MAX_FIELDS {
+            warn!("Announcement has {} fields once split, leaving out the last {}.", fields.len(), fields.len() - MAX_FIELDS);
+            fields.truncate(MAX_FIELDS);
+        }
+
+        trace!("Parsing embed color...");
+        let color = parse_color(color)?;
+
+        trace!("Assembling message...");
+        let optional = |value: &String| Some(value.clone()).filter(|value| !value.is_empty());
+        let mut message = webhook::models::Message::new();
+        if !content.is_empty() {
+            message.content(&content);
+        }
+        message.embed(|embed| {
+            if !author_name.is_empty() {
+                embed.author(&author_name, optional(&author_url), optional(&author_icon));
+            }
+            if !title.is_empty() {
+                embed.title(&title);
+            }
+            if let Some(color) = color {
+                embed.color(&color.to_string());
+            }
+            for (name, value, inline) in fields.iter() {
+                embed.field(name, value, *inline);
+            }
+            if !footer.is_empty() {
+                embed.footer(&footer, None);
+            }
+            if !timestamp.is_empty() {
+                embed.timestamp(&timestamp);
+            }
+            embed
+        });
+        Ok(message)
+    }
+
+    /// Render the message announcing a rank change of a guild member with the given context, reusing the author templates of match announcements.
+    pub fn render_rank_change<S: Serialize>(&self, ctx: &S) -> Result<webhook::models::Message, TemplateError> {
+        let author_name = self.render_one("author_name", ctx)?;
+        let author_url = self.render_one("author_url", ctx)?;
+        let author_icon = self.render_one("author_icon", ctx)?;
+        let title = self.render_one("rank_title", ctx)?;
+        let description = self.render_one("rank_description", ctx)?;
+        let color = self.render_one("rank_color", ctx)?;
+
+        trace!("Parsing embed color...");
+        let color = parse_color(color)?;
+
+        trace!("Assembling message...");
+        let optional = |value: &String| Some(value.clone()).filter(|value| !value.is_empty());
+        let mut message = webhook::models::Message::new();
+        message.embed(|embed| {
+            if !author_name.is_empty() {
+                embed.author(&author_name, optional(&author_url), optional(&author_icon));
+            }
+            if !title.is_empty() {
+                embed.title(&title);
+            }
+            if !description.is_empty() {
+                embed.description(&description);
+            }
+            if let Some(color) = color {
+                embed.color(&color.to_string());
+            }
+            embed
+        });
+        Ok(message)
+    }
+}
+
+/// Split the rendered `value` of a field at line breaks into chunks of at most [MAX_FIELD_VALUE] characters, to be displayed in consecutive fields.
+///
+/// Lines too long to fit in a field on their own are truncated.
+fn split_value(value: &str) -> Vec<String> {
+    let mut chunks: Vec<String> = Vec::new();
+    let mut chunk = String::new();
+    let mut length: usize = 0;
+    for line in value.lines() {
+        let line = truncate(line, MAX_FIELD_VALUE);
+        let line_length = line.chars().count();
+        if length > 0 && length + 1 + line_length > MAX_FIELD_VALUE {
+            chunks.push(std::mem::take(&mut chunk));
+            length = 0;
+        }
+        if length > 0 {
+            chunk.push('\n');
+            length += 1;
+        }
+        chunk.push_str(&line);
+        length += line_length;
+    }
+    chunks.push(chunk);
+    chunks.into_iter()
+        .map(|chunk| chunk.trim().to_string())
+        .filter(|chunk| !chunk.is_empty())
+        .collect()
+}
+
+/// Truncate `text` to at most `max` characters, ending it with an ellipsis if it was cut.
+fn truncate(text: &str, max: usize) -> String {
+    match text.chars().count() > max {
+        true => text.chars().take(max - 1).chain(std::iter::once('…')).collect(),
+        false => text.to_string(),
+    }
+}
+
+/// Parse the rendered `color` of an embed, which is either empty or an hexadecimal RGB value such as `#2ACB4F`.
+fn parse_color(color: String) -> Result<Option<u32>, TemplateError> {
+    match color.is_empty() {
+        true => Ok(None),
+        false => u32::from_str_radix(color.trim_start_matches('#'), 16).map(Some).map_err(|_| TemplateError::Color(color)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build the view of a guild member, with all the stats and items a full announcement can show.
+    fn player(index: usize) -> serde_json::Value {
+        let emoji = |name: &str| format!("<:{name}_{index}:1090000000000000000>");
+        serde_json::json!({
+            "guild_member": true,
+            "name": format!("Guild member number {index}"),
+            "hero": emoji("hero"),
+            "medal": {"name": "Immortal", "emoji": emoji("immortal")},
+            "kills": 12, "deaths": 3, "assists": 17, "imp": 42,
+            "party": 1,
+            "position": index,
+            "stats": [
+                {"name": "NW", "text": "25.3k"},
+                {"name": "GPM", "text": "734"},
+                {"name": "XPM", "text": "812"},
+            ],
+            "award": "MVP", "award_name": "MVP",
+            "items": (0..6).map(|slot| emoji(&format!("item{slot}"))).collect::<Vec<_>>(),
+            "backpack": (0..3).map(|slot| emoji(&format!("backpack{slot}"))).collect::<Vec<_>>(),
+            "neutral": emoji("neutral"),
+        })
+    }
+
+    #[test]
+    fn splits_full_stacks_with_items_across_fields() {
+        let renderer = Renderer::new(&TemplateConfig::default(), Catalog::load("en").unwrap()).unwrap();
+        let ctx = serde_json::json!({
+            "id": 1, "guild": {"id": 1, "name": "Guild", "logo": "logo"},
+            "result": "victory", "result_name": "Victory", "abandoned": false,
+            "lobby_type_name": "Ranked", "game_mode_name": "All Pick",
+            "duration": "42:00", "end": "2026-01-01T00:00:00Z",
+            "stack": 5, "full_roster": false, "awaiting_parse": false,
+            "radiant": (1..=5).map(player).collect::<Vec<_>>(),
+            "dire": [],
+        });
+
+        let message = renderer.render(&ctx).unwrap();
+
+        let fields = &message.embeds[0].fields;
+        assert!(fields.len() <= MAX_FIELDS);
+        assert!(fields.iter().all(|field| field.value.chars().count() <= MAX_FIELD_VALUE));
+        assert!(fields.iter().all(|field| !field.name.is_empty() && !field.value.is_empty()));
+        let radiant: Vec<_> = fields.iter().take_while(|field| field.name.contains("Radiant") || field.name == CONTINUATION_NAME).collect();
+        assert!(radiant.len() > 1);
+        assert!(radiant[1..].iter().all(|field| field.name == CONTINUATION_NAME && field.inline));
+        let value: String = radiant.iter().map(|field| field.value.as_str()).collect::<Vec<_>>().join("\n");
+        assert!((1..=5).all(|index| value.contains(&format!("Guild member number {index}"))));
+        assert!(value.contains("<:neutral_5:1090000000000000000>"));
+    }
+
+    #[test]
+    fn truncates_lines_too_long_for_a_field() {
+        let chunks = split_value(&format!("short\n{}", "x".repeat(2000)));
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0], "short");
+        assert_eq!(chunks[1].chars().count(), MAX_FIELD_VALUE);
+        assert!(chunks[1].ends_with('…'));
+    }
+}
@@ -0,0 +1,190 @@
+//! This module is about the `upload-emojis` subcommand, which uploads hero icons to Discord as custom emojis and writes the hero emoji table using them.
+//!
+//! Usage: `revenants_brooch upload-emojis <ICONS_DIR> <OUTPUT> [--guild <GUILD_ID>]`
+//!
+//! Icons are looked up in `ICONS_DIR` by the short name of the hero, such as `antimage.png`.
+//! They are uploaded as emojis of the application of the bot, or of the Discord server `GUILD_ID` if specified.
+//! Emojis already existing with the same name are reused instead of being uploaded again.
+//!
+//! Uploading to the application is recommended: it can hold up to 2000 emojis, while a server without boosts only has 50 static emoji slots,
+//! far fewer than the ~125 heroes, so uploading to a server fails once its slots are full.
+//!
+//! Configured through the `DISCORD_BOT_TOKEN`, `STRATZ_JWT` and optional `DISCORD_API_URL` and `STRATZ_API_URL` envvars; the latter two allow running against mock APIs.
+
+use std::path::{Path, PathBuf};
+use crate::discord::{DiscordError, EmojiOwner};
+use crate::emoji::EmojiTable;
+use crate::stratz::{RetryConfig, StratzError};
+use crate::{discord, stratz};
+
+/// The image file extensions icons are looked up with, and their MIME types.
+const EXTENSIONS: [(&str, &str); 4] = [("png", "image/png"), ("jpg", "image/jpeg"), ("gif", "image/gif"), ("webp", "image/webp")];
+
+/// Error enumeration for possible errors while uploading emojis.
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    /// The command line arguments are not valid.
+    #[error("{0}\nusage: revenants_brooch upload-emojis <ICONS_DIR> <OUTPUT> [--guild <GUILD_ID>]")]
+    Usage(String),
+    /// A required envvar is not set.
+    #[error("the {0} envvar must be set")]
+    Env(&'static str),
+    /// The hero constants could not be fetched from Stratz.
+    #[error("could not fetch heroes from Stratz: {0}")]
+    Stratz(#[from] StratzError),
+    /// A request to Discord failed.
+    #[error("could not upload emojis to Discord: {0}")]
+    Discord(#[from] DiscordError),
+    /// An icon could not be read, or the emoji table could not be written.
+    #[error("could not access {path:?}: {source}")]
+    Io {
+        /// The path of the file being accessed.
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// The arguments of the subcommand.
+#[derive(Clone, Debug)]
+struct Args {
+    icons_dir: PathBuf,
+    output: PathBuf,
+    guild_id: Option<String>,
+}
+
+impl Args {
+    /// Parse the arguments following the subcommand name.
+    fn parse(args: &[String]) -> Result<Self, UploadError> {
+        let mut positional: Vec<&String> = Vec::new();
+        let mut guild_id = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--guild" => match args.next() {
+                    Some(id) => guild_id = Some(id.clone()),
+                    None => return Err(UploadError::Usage(String::from("--guild requires a value"))),
+                },
+                _ if arg.starts_with("--") => return Err(UploadError::Usage(format!("unknown option {arg}"))),
+                _ => positional.push(arg),
+            }
+        }
+        match positional[..] {
+            [icons_dir, output] => Ok(Self { icons_dir: icons_dir.into(), output: output.into(), guild_id }),
+            _ => Err(UploadError::Usage(String::from("expected exactly two arguments"))),
+        }
+    }
+}
+
+/// Find the icon of the hero having the specified `short_name` in `dir`, returning its path and MIME type.
+fn find_icon(dir: &Path, short_name: &str) -> Option<(PathBuf, &'static str)> {
+    EXTENSIONS.iter()
+        .map(|(extension, mime)| (dir.join(format!("{short_name}.{extension}")), *mime))
+        .find(|(path, _)| path.is_file())
+}
+
+/// Run the subcommand with the arguments following its name.
+pub async fn run(args: &[String]) -> Result<(), UploadError> {
+    let args = Args::parse(args)?;
+    let token = std::env::var("DISCORD_BOT_TOKEN").map_err(|_| UploadError::Env("DISCORD_BOT_TOKEN"))?;
+    let jwt = std::env::var("STRATZ_JWT").map_err(|_| UploadError::Env("STRATZ_JWT"))?;
+    let discord_api_url = std::env::var("DISCORD_API_URL").unwrap_or_else(|_| String::from(discord::DEFAULT_API_URL));
+    let stratz_api_url = std::env::var("STRATZ_API_URL").unwrap_or_else(|_| String::from(stratz::DEFAULT_API_URL));
+
+    trace!("Creating new Stratz client...");
+    let stratz_client = stratz::Client::new(reqwest::Client::new(), stratz_api_url, jwt, RetryConfig::default());
+    trace!("Creating new Discord client...");
+    let discord_client = discord::Client::new(reqwest::Client::new(), discord_api_url, token);
+
+    let owner = match args.guild_id {
+        Some(id) => EmojiOwner::Guild(id),
+        None => EmojiOwner::Application(discord_client.application_id().await?),
+    };
+
+    trace!("Fetching heroes...");
+    let heroes = stratz_client.fetch_heroes().await?;
+    trace!("Listing existing emojis...");
+    let existing = discord_client.list_emojis(&owner).await?;
+
+    let mut table = EmojiTable::default();
+    for hero in heroes {
+        let (Some(id), Some(short_name)) = (hero.id, hero.short_name.clone()) else {
+            warn!("Skipping hero without an ID or a short name: {hero:?}");
+            continue
+        };
+        if let Some(emoji) = existing.iter().find(|emoji| emoji.name == short_name) {
+            debug!("Reusing existing emoji for {short_name}.");
+            table.insert(id.into(), emoji.markdown());
+            continue
+        }
+        let Some((path, mime)) = find_icon(&args.icons_dir, &short_name) else {
+            warn!("No icon found for {short_name} in {:?}, its name will be displayed instead.", &args.icons_dir);
+            continue
+        };
+        trace!("Reading icon {path:?}...");
+        let image = std::fs::read(&path).map_err(|source| UploadError::Io { path: path.clone(), source })?;
+        let emoji = discord_client.create_emoji(&owner, &short_name, mime, &image).await?;
+        info!("Uploaded emoji for {short_name}: {}", emoji.markdown());
+        table.insert(id.into(), emoji.markdown());
+    }
+
+    trace!("Writing emoji table to {:?}...", &args.output);
+    let contents = format!("# Mapping of hero IDs to the Discord emojis displayed in announcements.\n# Generated by `revenants_brooch upload-emojis`.\n\n{}", table.to_toml());
+    std::fs::write(&args.output, contents).map_err(|source| UploadError::Io { path: args.output.clone(), source })?;
+    info!("Wrote emoji table to {:?}, set emojis.heroes to it to use it.", &args.output);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use super::*;
+
+    #[tokio::test]
+    async fn uploads_missing_emojis_and_reuses_existing_ones() {
+        let mut server = mockito::Server::new_async().await;
+        let dir = std::env::temp_dir().join(format!("revenants_brooch-upload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("axe.png"), b"axe icon").unwrap();
+        let output = dir.join("heroes.toml");
+
+        let heroes = server.mock("POST", "/graphql")
+            .match_header("authorization", "Bearer jwt")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"data": {"constants": {"heroes": [
+                {"id": 1, "displayName": "Anti-Mage", "shortName": "antimage"},
+                {"id": 2, "displayName": "Axe", "shortName": "axe"},
+                {"id": 3, "displayName": "Bane", "shortName": "bane"}
+            ]}}}"#)
+            .create_async().await;
+        let application = server.mock("GET", "/applications/@me")
+            .match_header("authorization", "Bot token")
+            .with_body(r#"{"id": "42"}"#)
+            .create_async().await;
+        let existing = server.mock("GET", "/applications/42/emojis")
+            .with_body(r#"{"items": [{"id": "100", "name": "antimage"}]}"#)
+            .create_async().await;
+        let created = server.mock("POST", "/applications/42/emojis")
+            .match_body(Matcher::PartialJsonString(r#"{"name": "axe"}"#.into()))
+            .with_body(r#"{"id": "200", "name": "axe"}"#)
+            .expect(1)
+            .create_async().await;
+
+        std::env::set_var("DISCORD_BOT_TOKEN", "token");
+        std::env::set_var("STRATZ_JWT", "jwt");
+        std::env::set_var("DISCORD_API_URL", server.url());
+        std::env::set_var("STRATZ_API_URL", format!("{}/graphql", server.url()));
+        let args = [dir.to_string_lossy().into_owned(), output.to_string_lossy().into_owned()];
+        run(&args).await.unwrap();
+
+        heroes.assert_async().await;
+        application.assert_async().await;
+        existing.assert_async().await;
+        created.assert_async().await;
+        let table = std::fs::read_to_string(&output).unwrap();
+        assert!(table.contains("1 = \"<:antimage:100>\"\n"));
+        assert!(table.contains("2 = \"<:axe:200>\"\n"));
+        assert!(!table.contains("3 = "));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}